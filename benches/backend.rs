@@ -0,0 +1,43 @@
+use std::time::{Duration, Instant};
+
+use tpc::{backend::Backend, engine, register};
+
+const BENCHMARKS: [(&str, &str); 3] = [
+    (
+        "fibonacci",
+        "fn fib(n) { n < 2 ? n : fib(n - 1) + fib(n - 2) }; fib(18)",
+    ),
+    (
+        "counter",
+        "fn count(i, n) { i = i + 1; i < n ? count(i, n) : i }; count(0, 60)",
+    ),
+    (
+        "arithmetic",
+        "fn poly(x, n) { n == 0 ? x : poly((x * 3 + 7) % 1000 - 1, n - 1) }; poly(1, 60)",
+    ),
+];
+
+fn measure<B: Backend>(backend: &mut B, source: &str) -> Duration {
+    let program = backend
+        .compile(source)
+        .ok()
+        .expect("benchmark source should compile");
+    let mut iterations = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(500) {
+        backend.run(&program).ok().expect("benchmark should run");
+        iterations += 1;
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    for (name, source) in BENCHMARKS {
+        let stack = measure(&mut engine::new(), source);
+        let register = measure(&mut register::engine::new(), source);
+        println!(
+            "{name:<12} stack {stack:>10.2?}  register {register:>10.2?}  speedup {:.2}x",
+            stack.as_secs_f64() / register.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+use tpc::{
+    compiler::{self, Tables},
+    decode::{self, Decoded},
+    impls::{data_stack, slice_reader, static_data, token_stream},
+    peephole, state, vm,
+};
+
+const BENCHMARKS: [(&str, &str); 4] = [
+    (
+        "fibonacci",
+        "fn fib(n) { n < 2 ? n : fib(n - 1) + fib(n - 2) }; fib(18)",
+    ),
+    (
+        "counter",
+        "fn count(i, n) { i = i + 1; i < n ? count(i, n) : i }; count(0, 60)",
+    ),
+    (
+        "arithmetic",
+        "fn poly(x, n) { n == 0 ? x : poly((x * 3 + 7) % 1000 - 1, n - 1) }; poly(1, 60)",
+    ),
+    (
+        "bigint",
+        "fn big(n) { n == 0 ? 0 : 100000000000000000000 % (n + 7) + big(n - 1) }; big(60)",
+    ),
+];
+
+fn compile(source: &str) -> (Vec<u8>, Tables) {
+    let mut stream = token_stream::new(slice_reader::new(source.as_bytes()));
+    let mut code = Vec::new();
+    let tables = compiler::compile_with(&mut stream, &mut code, &(), &mut ())
+        .ok()
+        .expect("benchmark source should compile");
+    (code, tables)
+}
+
+fn measure(mut run: impl FnMut()) -> Duration {
+    let mut iterations = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(500) {
+        run();
+        iterations += 1;
+    }
+    start.elapsed() / iterations
+}
+
+fn bytes(code: &Vec<u8>) -> Duration {
+    let mut state = state::State::new(data_stack::new(static_data::new::<256>()));
+    measure(|| {
+        state.reset();
+        vm::run(&mut state, code)
+            .ok()
+            .expect("benchmark should run");
+    })
+}
+
+fn decoded(program: &Decoded) -> Duration {
+    let mut state = state::State::new(data_stack::new(static_data::new::<256>()));
+    measure(|| {
+        state.reset();
+        let status = vm::execute(&mut state, program).ok();
+        assert!(
+            matches!(status, Some(vm::Status::Finished(_))),
+            "benchmark should run"
+        );
+    })
+}
+
+fn main() {
+    for (name, source) in BENCHMARKS {
+        let (code, tables) = compile(source);
+        let (optimized, tables) = peephole::optimize(code.clone(), tables);
+        let program =
+            decode::decode(&optimized, &tables.handlers).expect("benchmark code should decode");
+        let plain = bytes(&code);
+        let fast = bytes(&optimized);
+        let typed = decoded(&program);
+        println!(
+            "{name:<12} unoptimized {plain:>10.2?}  optimized {fast:>10.2?} ({:.2}x)  decoded {typed:>10.2?} ({:.2}x)",
+            plain.as_secs_f64() / fast.as_secs_f64(),
+            fast.as_secs_f64() / typed.as_secs_f64()
+        );
+    }
+}
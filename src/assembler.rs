@@ -0,0 +1,273 @@
+use core::fmt;
+use std::collections::HashMap;
+
+use crate::{
+    opcode::{self, Operand, CLN, LDC},
+    push::{PatchByte, PatchData, PushByte, PushData},
+};
+
+pub struct AssembleError {
+    pub line: usize,
+    pub message: Box<str>,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Default)]
+pub struct Pool {
+    pub natives: Vec<Box<str>>,
+    pub globals: Vec<Box<str>>,
+    pub constants: Vec<f64>,
+}
+
+fn split(line: &str) -> Result<Vec<&str>, Box<str>> {
+    let mut words = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() && !rest.starts_with(';') {
+        let end = match rest.as_bytes()[0] {
+            b'"' => {
+                let mut escaped = false;
+                let end = rest[1..].find(|c| {
+                    let found = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    found
+                });
+                end.ok_or("Unterminated string.")? + 2
+            }
+            b'[' => rest.find(']').ok_or("Unterminated list.")? + 1,
+            _ => rest
+                .find(|c: char| c.is_whitespace() || c == ';')
+                .unwrap_or(rest.len()),
+        };
+        words.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    Ok(words)
+}
+
+fn parse_integer<T: TryFrom<i128>>(word: &str) -> Option<T> {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, word),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i128>().ok()?,
+    };
+    T::try_from(if negative { -value } else { value }).ok()
+}
+
+fn parse_string(word: &str) -> Option<String> {
+    let mut chars = word.strip_prefix('"')?.strip_suffix('"')?.chars();
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        text.push(match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'u' => {
+                let code: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+            }
+            c => c,
+        });
+    }
+    Some(text)
+}
+
+struct Assembler<'a, P> {
+    builder: &'a mut P,
+    offset: usize,
+    labels: HashMap<&'a str, usize>,
+    fixups: Vec<(usize, &'a str, usize)>,
+    pool: Pool,
+}
+
+impl<'a, P: PushByte + PatchByte> Assembler<'a, P> {
+    fn push_data<T>(&mut self, value: T)
+    where
+        P: PushData<T>,
+    {
+        self.builder.push_data(value);
+        self.offset += core::mem::size_of::<T>();
+    }
+
+    fn index(&self, opcode: u8, word: &str) -> Option<u16> {
+        let names = match opcode {
+            CLN => &self.pool.natives,
+            LDC => return parse_integer(word),
+            _ => &self.pool.globals,
+        };
+        match names.iter().position(|name| name.as_ref() == word) {
+            Some(index) => Some(index as u16),
+            None => parse_integer(word),
+        }
+    }
+
+    fn operand(
+        &mut self,
+        opcode: u8,
+        operand: Operand,
+        word: &'a str,
+        line: usize,
+    ) -> Result<(), Box<str>> {
+        let invalid = || format!("Invalid {operand:?} operand '{word}'.").into_boxed_str();
+        match operand {
+            Operand::Byte => self.push_data(parse_integer::<u8>(word).ok_or_else(invalid)?),
+            Operand::Opcode => self.push_data(opcode::find(word).ok_or_else(invalid)?),
+            Operand::Index => self.push_data(self.index(opcode, word).ok_or_else(invalid)?),
+            Operand::Address => match parse_integer::<u32>(word) {
+                Some(address) => self.push_data(address),
+                None => {
+                    self.fixups.push((self.offset, word, line));
+                    self.push_data(0u32);
+                }
+            },
+            Operand::Int8 => self.push_data(parse_integer::<i8>(word).ok_or_else(invalid)?),
+            Operand::Int16 => self.push_data(parse_integer::<i16>(word).ok_or_else(invalid)?),
+            Operand::Int32 => self.push_data(parse_integer::<i32>(word).ok_or_else(invalid)?),
+            Operand::Integer => self.push_data(parse_integer::<i64>(word).ok_or_else(invalid)?),
+            Operand::Real => self.push_data(word.parse::<f64>().map_err(|_| invalid())?),
+            Operand::Limbs => {
+                let items = word
+                    .strip_prefix('[')
+                    .and_then(|word| word.strip_suffix(']'))
+                    .ok_or_else(invalid)?;
+                let limbs = items
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(parse_integer::<u32>)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?;
+                if limbs.len() > u16::MAX as usize {
+                    return Err(invalid());
+                }
+                self.push_data(limbs.len() as u16);
+                for limb in limbs {
+                    self.push_data(limb);
+                }
+            }
+            Operand::Name => {
+                let text = parse_string(word).ok_or_else(invalid)?;
+                if text.len() > u16::MAX as usize {
+                    return Err(invalid());
+                }
+                self.push_data(text.len() as u16);
+                for byte in text.bytes() {
+                    self.push_data(byte);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, words: &[&str]) -> Result<(), Box<str>> {
+        if words[0] == ".constant" {
+            return match words[1..] {
+                [word] if self.pool.constants.len() <= u16::MAX as usize => {
+                    let value = word
+                        .parse::<f64>()
+                        .map_err(|_| format!("Invalid constant '{word}'."))?;
+                    self.pool.constants.push(value);
+                    Ok(())
+                }
+                _ => Err("Expected one constant value.".into()),
+            };
+        }
+        let (names, kind) = match words[0] {
+            ".native" => (&mut self.pool.natives, "native"),
+            ".global" => (&mut self.pool.globals, "global"),
+            directive => return Err(format!("Unknown directive '{directive}'.").into()),
+        };
+        match words[1..] {
+            [name] if names.iter().any(|n| n.as_ref() == name) => {
+                Err(format!("Duplicate {kind} '{name}'.").into())
+            }
+            [name] if names.len() <= u16::MAX as usize => {
+                names.push(name.into());
+                Ok(())
+            }
+            _ => Err(format!("Expected one {kind} name.").into()),
+        }
+    }
+
+    fn line(&mut self, text: &'a str, line: usize) -> Result<(), Box<str>> {
+        let mut words = split(text)?;
+        if let Some(label) = words.first().and_then(|word| word.strip_suffix(':')) {
+            if self.labels.insert(label, self.offset).is_some() {
+                return Err(format!("Duplicate label '{label}'.").into());
+            }
+            words.remove(0);
+        }
+        let (mnemonic, operands) = match words.split_first() {
+            Some((mnemonic, operands)) => (*mnemonic, operands),
+            None => return Ok(()),
+        };
+        if mnemonic.starts_with('.') {
+            return self.directive(&words);
+        }
+        let opcode = opcode::find(&mnemonic.to_ascii_uppercase())
+            .ok_or_else(|| format!("Unknown mnemonic '{mnemonic}'."))?;
+        let info = opcode::info(opcode).ok_or("Unknown opcode.")?;
+        if operands.len() != info.operands.len() {
+            return Err(format!(
+                "'{}' expects {} operand(s), found {}.",
+                info.name,
+                info.operands.len(),
+                operands.len()
+            )
+            .into());
+        }
+        self.push_data(opcode);
+        for (&kind, &word) in info.operands.iter().zip(operands) {
+            self.operand(opcode, kind, word, line)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn assemble<P: PushByte + PatchByte>(
+    source: &str,
+    builder: &mut P,
+) -> Result<Pool, AssembleError> {
+    let mut assembler = Assembler {
+        builder,
+        offset: 0,
+        labels: HashMap::new(),
+        fixups: Vec::new(),
+        pool: Pool::default(),
+    };
+    for (index, text) in source.lines().enumerate() {
+        assembler
+            .line(text, index + 1)
+            .map_err(|message| AssembleError {
+                line: index + 1,
+                message,
+            })?;
+    }
+    for (address, label, line) in core::mem::take(&mut assembler.fixups) {
+        match assembler.labels.get(label) {
+            Some(&target) => assembler.builder.patch_data(address, target as u32),
+            None => {
+                return Err(AssembleError {
+                    line,
+                    message: format!("Unknown label '{label}'.").into(),
+                })
+            }
+        }
+    }
+    Ok(assembler.pool)
+}
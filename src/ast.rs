@@ -0,0 +1,124 @@
+use crate::{bigint::BigInt, token::Pos};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    FloorDivide,
+    EuclidModulo,
+    Power,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ComparisonOperator {
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub kind: Kind,
+    pub pos: Pos,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Catch {
+    pub name: Box<str>,
+    pub name_pos: Pos,
+    pub body: Box<Node>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    Void,
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    BigInteger(BigInt),
+    Real(f64),
+    Yield,
+    Variable(Box<str>),
+    Assign {
+        name: Box<str>,
+        name_pos: Pos,
+        value: Box<Node>,
+    },
+    Call {
+        name: Box<str>,
+        arguments: Vec<Node>,
+    },
+    Field {
+        object: Box<Node>,
+        name: Box<str>,
+        access_pos: Pos,
+        safe: bool,
+    },
+    Method {
+        receiver: Box<Node>,
+        name: Box<str>,
+        arguments: Vec<Node>,
+        access_pos: Pos,
+        safe: bool,
+    },
+    Unwrap {
+        operand: Box<Node>,
+        operator_pos: Pos,
+    },
+    Negate {
+        operand: Box<Node>,
+        operator_pos: Pos,
+    },
+    Binary {
+        operator: BinaryOperator,
+        operator_pos: Pos,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+    Comparison {
+        first: Box<Node>,
+        rest: Vec<(ComparisonOperator, Pos, Node)>,
+    },
+    Coalesce {
+        operator_pos: Pos,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+    Conditional {
+        condition: Box<Node>,
+        question_pos: Pos,
+        then: Box<Node>,
+        colon_pos: Pos,
+        otherwise: Box<Node>,
+    },
+    Sequence(Vec<Node>),
+    Result {
+        ok: bool,
+        value: Box<Node>,
+    },
+    Throw(Box<Node>),
+    Return(Box<Node>),
+    Try {
+        keyword_pos: Pos,
+        body: Box<Node>,
+        catch: Option<Catch>,
+        finally: Option<Box<Node>>,
+    },
+    Function {
+        name: Box<str>,
+        name_pos: Pos,
+        parameters: Vec<Box<str>>,
+        body: Box<Node>,
+    },
+}
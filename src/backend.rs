@@ -0,0 +1,42 @@
+use crate::{
+    engine::{self, Engine, Error},
+    register::{self, engine::Machine},
+    state::Stack,
+    value::Value,
+};
+
+pub trait Backend {
+    type Program;
+
+    fn compile(&mut self, source: &str) -> Result<Self::Program, Error>;
+    fn run(&mut self, program: &Self::Program) -> Result<Value, Error>;
+
+    fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let program = self.compile(source)?;
+        self.run(&program)
+    }
+}
+
+impl<S: Stack> Backend for Engine<S> {
+    type Program = engine::Program;
+
+    fn compile(&mut self, source: &str) -> Result<Self::Program, Error> {
+        Engine::compile(self, source)
+    }
+
+    fn run(&mut self, program: &Self::Program) -> Result<Value, Error> {
+        Engine::run(self, program)
+    }
+}
+
+impl<S: Stack> Backend for Machine<S> {
+    type Program = register::Program;
+
+    fn compile(&mut self, source: &str) -> Result<Self::Program, Error> {
+        Machine::compile(self, source)
+    }
+
+    fn run(&mut self, program: &Self::Program) -> Result<Value, Error> {
+        Machine::run(self, program)
+    }
+}
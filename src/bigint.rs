@@ -0,0 +1,397 @@
+use core::{cmp::Ordering, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+fn trim(magnitude: &mut Vec<u32>) {
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+}
+
+fn compare_magnitude(l: &[u32], r: &[u32]) -> Ordering {
+    l.len()
+        .cmp(&r.len())
+        .then_with(|| l.iter().rev().cmp(r.iter().rev()))
+}
+
+fn add_magnitude(l: &[u32], r: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(l.len().max(r.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..l.len().max(r.len()) {
+        let sum = *l.get(i).unwrap_or(&0) as u64 + *r.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry != 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+fn sub_magnitude(l: &[u32], r: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(l.len());
+    let mut borrow = 0i64;
+    for (i, &d) in l.iter().enumerate() {
+        let mut difference = d as i64 - *r.get(i).unwrap_or(&0) as i64 - borrow;
+        if difference < 0 {
+            difference += 1 << 32;
+            borrow = 1;
+        } else {
+            borrow = 0;
+        }
+        result.push(difference as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_magnitude(l: &[u32], r: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; l.len() + r.len()];
+    for (i, &a) in l.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &b) in r.iter().enumerate() {
+            let product = a as u64 * b as u64 + result[i + j] as u64 + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + r.len()] = carry as u32;
+    }
+    trim(&mut result);
+    result
+}
+
+fn div_rem_small(l: &[u32], r: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; l.len()];
+    let mut remainder = 0u64;
+    for i in (0..l.len()).rev() {
+        let current = (remainder << 32) | l[i] as u64;
+        quotient[i] = (current / r as u64) as u32;
+        remainder = current % r as u64;
+    }
+    trim(&mut quotient);
+    (quotient, remainder as u32)
+}
+
+fn div_rem_magnitude(l: &[u32], r: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if r.len() == 1 {
+        let (quotient, remainder) = div_rem_small(l, r[0]);
+        let mut remainder = vec![remainder];
+        trim(&mut remainder);
+        return (quotient, remainder);
+    }
+    let mut quotient = vec![0u32; l.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+    for bit in (0..l.len() * 32).rev() {
+        remainder = shl_magnitude(&remainder, 1);
+        if (l[bit / 32] >> (bit % 32)) & 1 == 1 {
+            if remainder.is_empty() {
+                remainder.push(1);
+            } else {
+                remainder[0] |= 1;
+            }
+        }
+        if compare_magnitude(&remainder, r) != Ordering::Less {
+            remainder = sub_magnitude(&remainder, r);
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    trim(&mut quotient);
+    (quotient, remainder)
+}
+
+fn shl_magnitude(l: &[u32], n: u32) -> Vec<u32> {
+    if l.is_empty() {
+        return Vec::new();
+    }
+    let limbs = (n / 32) as usize;
+    let bits = n % 32;
+    let mut result = vec![0u32; limbs];
+    let mut carry = 0u32;
+    for &d in l {
+        if bits == 0 {
+            result.push(d);
+        } else {
+            result.push((d << bits) | carry);
+            carry = d >> (32 - bits);
+        }
+    }
+    if carry != 0 {
+        result.push(carry);
+    }
+    result
+}
+
+fn shr_magnitude(l: &[u32], n: u32) -> Vec<u32> {
+    let limbs = (n / 32) as usize;
+    if limbs >= l.len() {
+        return Vec::new();
+    }
+    let bits = n % 32;
+    let mut result = Vec::with_capacity(l.len() - limbs);
+    for i in limbs..l.len() {
+        let high = if bits == 0 {
+            0
+        } else {
+            l.get(i + 1).map_or(0, |d| d << (32 - bits))
+        };
+        result.push((l[i] >> bits) | high);
+    }
+    trim(&mut result);
+    result
+}
+
+impl BigInt {
+    fn new(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        trim(&mut magnitude);
+        Self {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    pub fn from_i64(value: i64) -> Self {
+        let abs = value.unsigned_abs();
+        Self::new(value < 0, vec![abs as u32, (abs >> 32) as u32])
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let abs = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &d| (acc << 32) | d as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(abs)
+        } else {
+            i64::try_from(abs).ok()
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let abs = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0f64, |acc, &d| acc * 4294967296.0 + d as f64);
+        if self.negative {
+            -abs
+        } else {
+            abs
+        }
+    }
+
+    pub fn parse(digits: &str) -> Option<Self> {
+        let mut magnitude = Vec::new();
+        for c in digits.bytes() {
+            if !c.is_ascii_digit() {
+                return None;
+            }
+            magnitude = add_magnitude(&mul_magnitude(&magnitude, &[10]), &[(c - b'0') as u32]);
+        }
+        Some(Self::new(false, magnitude))
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn negate(&self) -> Self {
+        Self::new(!self.negative, self.magnitude.clone())
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        if self.negative == other.negative {
+            return Self::new(
+                self.negative,
+                add_magnitude(&self.magnitude, &other.magnitude),
+            );
+        }
+        match compare_magnitude(&self.magnitude, &other.magnitude) {
+            Ordering::Less => Self::new(
+                other.negative,
+                sub_magnitude(&other.magnitude, &self.magnitude),
+            ),
+            _ => Self::new(
+                self.negative,
+                sub_magnitude(&self.magnitude, &other.magnitude),
+            ),
+        }
+    }
+
+    pub fn subtract(&self, other: &Self) -> Self {
+        self.add(&other.negate())
+    }
+
+    pub fn multiply(&self, other: &Self) -> Self {
+        Self::new(
+            self.negative != other.negative,
+            mul_magnitude(&self.magnitude, &other.magnitude),
+        )
+    }
+
+    pub fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = div_rem_magnitude(&self.magnitude, &other.magnitude);
+        Some((
+            Self::new(self.negative != other.negative, quotient),
+            Self::new(self.negative, remainder),
+        ))
+    }
+
+    pub fn div_rem_floor(&self, other: &Self) -> Option<(Self, Self)> {
+        let (quotient, remainder) = self.div_rem(other)?;
+        if !remainder.is_zero() && remainder.negative != other.negative {
+            Some((quotient.subtract(&Self::from_i64(1)), remainder.add(other)))
+        } else {
+            Some((quotient, remainder))
+        }
+    }
+
+    pub fn rem_euclid(&self, other: &Self) -> Option<Self> {
+        let (_, remainder) = self.div_rem(other)?;
+        if remainder.negative {
+            Some(Self::new(
+                false,
+                sub_magnitude(&other.magnitude, &remainder.magnitude),
+            ))
+        } else {
+            Some(remainder)
+        }
+    }
+
+    pub fn power(&self, mut exponent: u32) -> Self {
+        let mut result = Self::from_i64(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.multiply(&base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.multiply(&base);
+            }
+        }
+        result
+    }
+
+    pub fn shift_left(&self, n: u32) -> Self {
+        Self::new(self.negative, shl_magnitude(&self.magnitude, n))
+    }
+
+    pub fn shift_right(&self, n: u32) -> Self {
+        if self.negative {
+            let one = Self::from_i64(1);
+            let shifted = shr_magnitude(&self.negate().subtract(&one).magnitude, n);
+            Self::new(false, shifted).add(&one).negate()
+        } else {
+            Self::new(false, shr_magnitude(&self.magnitude, n))
+        }
+    }
+
+    pub fn parts(&self) -> (bool, &[u32]) {
+        (self.negative, &self.magnitude)
+    }
+
+    pub fn from_parts(negative: bool, magnitude: Vec<u32>) -> Self {
+        Self::new(negative, magnitude)
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitude(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitude(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let mut chunks = Vec::new();
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, remainder) = div_rem_small(&magnitude, 1_000_000_000);
+            chunks.push(remainder);
+            magnitude = quotient;
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        if let Some(first) = chunks.next() {
+            write!(f, "{first}")?;
+        }
+        for chunk in chunks {
+            write!(f, "{chunk:09}")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn arithmetic_test() {
+    let values: [i128; 8] = [
+        0,
+        1,
+        -7,
+        u32::MAX as i128,
+        i64::MAX as i128 + 3,
+        i64::MIN as i128 * 5,
+        -(1 << 95) + 12345,
+        (1 << 100) - 1,
+    ];
+    let big = |v: i128| {
+        let negative = v < 0;
+        let abs = v.unsigned_abs();
+        let magnitude = (0..4).map(|i| (abs >> (i * 32)) as u32).collect();
+        BigInt::from_parts(negative, magnitude)
+    };
+    for &l in &values {
+        assert_eq!(big(l).to_string(), l.to_string());
+        assert_eq!(
+            BigInt::parse(&l.unsigned_abs().to_string()),
+            Some(big(l.abs()))
+        );
+        assert_eq!(big(l).shift_right(7), big(l >> 7));
+        assert_eq!(big(l).shift_left(13).shift_right(13), big(l));
+        for &r in &values {
+            assert_eq!(big(l).add(&big(r)), big(l + r));
+            assert_eq!(big(l).subtract(&big(r)), big(l - r));
+            assert_eq!(big(l).cmp(&big(r)), l.cmp(&r));
+            if let Some(product) = l.checked_mul(r) {
+                assert_eq!(big(l).multiply(&big(r)), big(product));
+            }
+            if r != 0 {
+                assert_eq!(big(l).div_rem(&big(r)), Some((big(l / r), big(l % r))));
+                assert_eq!(big(l).rem_euclid(&big(r)), Some(big(l.rem_euclid(r))));
+                let floor = big(l.div_euclid(r) - ((r < 0 && l.rem_euclid(r) != 0) as i128));
+                assert_eq!(big(l).div_rem_floor(&big(r)).map(|(q, _)| q), Some(floor));
+            }
+        }
+    }
+}
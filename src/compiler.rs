@@ -1,8 +1,15 @@
 use core::fmt;
 
 use crate::{
+    ast::*,
+    bigint::BigInt,
+    fold,
+    handler::{self, Handler, HandlerTable},
+    line::LineTable,
+    native::Natives,
     opcode::*,
-    push::{PushByte, PushData},
+    parser,
+    push::{PatchByte, PatchData, PushByte, PushData},
     token::*,
 };
 
@@ -11,6 +18,21 @@ pub trait Stream {
     fn next(&mut self) -> Option<TokenAndPos>;
 }
 
+pub trait Globals {
+    fn find(&self, name: &str) -> Option<u16>;
+    fn define(&mut self, name: &str) -> Option<u16>;
+}
+
+impl Globals for () {
+    fn find(&self, _name: &str) -> Option<u16> {
+        None
+    }
+
+    fn define(&mut self, _name: &str) -> Option<u16> {
+        None
+    }
+}
+
 pub enum Message {
     Owned(Box<str>),
     Static(&'static str),
@@ -42,181 +64,762 @@ pub struct CompileError {
     pub pos: Pos,
 }
 
+pub struct Warning {
+    pub message: Message,
+    pub pos: Pos,
+}
+
 pub type CompileResult = Result<(), CompileError>;
 
-fn primary<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    match stream.next() {
-        Some(token_and_pos) => match token_and_pos.token {
-            Token::Integer(value) => {
-                builder.push_byte(LDI);
-                builder.push_data(value);
-                Ok(())
-            }
-            Token::Real(value) => {
-                builder.push_byte(LDR);
-                builder.push_data(value);
-                Ok(())
-            }
-            Token::Single(c) => Err(CompileError {
-                message: format!("Expected value, found character {}.", c as char).into(),
-                pos: token_and_pos.pos,
-            }),
-            Token::Double(c0, c1) => Err(CompileError {
-                message: format!("Expected value, found token {}{}.", c0 as char, c1 as char)
-                    .into(),
-                pos: token_and_pos.pos,
-            }),
-        },
+#[derive(Clone)]
+pub struct Function {
+    pub name: Box<str>,
+    pub address: usize,
+    pub arity: u8,
+}
+
+pub struct Tables {
+    pub lines: LineTable,
+    pub handlers: HandlerTable,
+    pub functions: Vec<Function>,
+    pub constants: Vec<f64>,
+    pub warnings: Vec<Warning>,
+}
+
+fn effect(opcode: u8) -> isize {
+    match (opcode, info(opcode)) {
+        (THR | RET, _) => 0,
+        (_, Some(info)) => info.pushes as isize - info.pops as isize,
+        (_, None) => 0,
+    }
+}
+
+struct Context<'a, P> {
+    builder: &'a mut P,
+    natives: &'a dyn Natives,
+    globals: &'a mut dyn Globals,
+    lines: LineTable,
+    handlers: HandlerTable,
+    functions: Vec<Function>,
+    bodies: Vec<Pos>,
+    finally: usize,
+    locals: Option<Vec<Box<str>>>,
+    constants: Option<Vec<f64>>,
+    offset: usize,
+    depth: usize,
+}
+
+impl<P: PushByte + PatchByte> Context<'_, P> {
+    fn push_byte(&mut self, value: u8) {
+        self.builder.push_byte(value);
+        self.offset += 1;
+    }
+
+    fn push_data<T>(&mut self, value: T)
+    where
+        P: PushData<T>,
+    {
+        self.builder.push_data(value);
+        self.offset += core::mem::size_of::<T>();
+    }
+
+    fn emit(&mut self, opcode: u8, pos: Pos) {
+        self.lines.push(self.offset, pos);
+        self.push_byte(opcode);
+        self.depth = self.depth.wrapping_add_signed(effect(opcode));
+    }
+
+    fn emit_jump(&mut self, opcode: u8, pos: Pos) -> usize {
+        self.emit(opcode, pos);
+        let address = self.offset;
+        self.push_data(0u32);
+        address
+    }
+
+    fn patch_jump(&mut self, address: usize) {
+        self.builder.patch_data(address, self.offset as u32);
+    }
+}
+
+fn call_native<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    name: &str,
+    name_pos: Pos,
+    pos: Pos,
+    arguments: &[Node],
+    mut count: usize,
+) -> CompileResult {
+    let native = match context.natives.find(name) {
+        Some(native) => native,
+        None => {
+            return Err(CompileError {
+                message: format!("Unknown native function '{name}'.").into(),
+                pos: name_pos,
+            })
+        }
+    };
+    for argument in arguments {
+        generate(context, argument)?;
+        count += 1;
+    }
+    if count > u8::MAX as usize || count != native.arity as usize {
+        return Err(CompileError {
+            message: format!(
+                "Native function '{name}' expects {} argument(s), found {count}.",
+                native.arity
+            )
+            .into(),
+            pos,
+        });
+    }
+    context.depth -= count;
+    context.emit(CLN, pos);
+    context.push_data(native.index);
+    context.push_data(native.arity);
+    Ok(())
+}
+
+enum Variable {
+    Local(u8),
+    Global(u16),
+}
+
+fn define<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    name: &str,
+    pos: Pos,
+) -> Result<Variable, CompileError> {
+    if let Some(locals) = &mut context.locals {
+        if let Some(index) = locals.iter().position(|local| local.as_ref() == name) {
+            return Ok(Variable::Local(index as u8));
+        }
+        if locals.len() < u8::MAX as usize {
+            locals.push(name.into());
+            return Ok(Variable::Local((locals.len() - 1) as u8));
+        }
+        return Err(CompileError {
+            message: format!("Unable to define local variable '{name}'.").into(),
+            pos,
+        });
+    }
+    match context.globals.define(name) {
+        Some(index) => Ok(Variable::Global(index)),
         None => Err(CompileError {
-            message: "Unexpected end of code.".into(),
-            pos: 0..0,
+            message: format!("Unable to define global variable '{name}'.").into(),
+            pos,
         }),
     }
 }
 
-fn multiple_binary_helper<S: Stream, P: PushByte, N, M>(
-    stream: &mut S,
-    builder: &mut P,
-    next: N,
-    mapper: M,
-) -> CompileResult
-where
-    N: Fn(&mut S, &mut P) -> CompileResult,
-    M: Fn(&Token) -> Option<u8>,
-{
-    next(stream, builder)?;
-    while let Some(token_and_pos) = stream.peek() {
-        if let Some(opcode) = mapper(&token_and_pos.token) {
-            let _pos = token_and_pos.pos.clone();
-            stream.next();
-            next(stream, builder)?;
-            builder.push_byte(opcode);
-        } else {
-            break;
+fn store<P: PushByte + PatchByte>(context: &mut Context<P>, variable: Variable, pos: Pos) {
+    match variable {
+        Variable::Local(index) => {
+            context.emit(STL, pos);
+            context.push_data(index);
+        }
+        Variable::Global(index) => {
+            context.emit(STG, pos);
+            context.push_data(index);
         }
     }
+}
+
+fn variable<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    name: &str,
+    pos: Pos,
+) -> CompileResult {
+    let local = context
+        .locals
+        .as_ref()
+        .and_then(|locals| locals.iter().position(|local| local.as_ref() == name));
+    if let Some(index) = local {
+        context.emit(LDL, pos);
+        context.push_data(index as u8);
+        return Ok(());
+    }
+    match context.globals.find(name) {
+        Some(index) => {
+            context.emit(LDG, pos);
+            context.push_data(index);
+            Ok(())
+        }
+        None => Err(CompileError {
+            message: format!("Unknown name '{name}'.").into(),
+            pos,
+        }),
+    }
+}
+
+fn call_function<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    index: usize,
+    arguments: &[Node],
+    pos: Pos,
+) -> CompileResult {
+    for argument in arguments {
+        generate(context, argument)?;
+    }
+    let count = arguments.len();
+    let function = &context.functions[index];
+    if count != function.arity as usize {
+        return Err(CompileError {
+            message: format!(
+                "Function '{}' expects {} argument(s), found {count}.",
+                function.name, function.arity
+            )
+            .into(),
+            pos,
+        });
+    }
+    let (address, arity) = (function.address as u32, function.arity);
+    context.depth -= count;
+    context.emit(CAL, pos);
+    context.push_data(address);
+    context.push_data(arity);
     Ok(())
 }
 
-fn single_binary_helper<S: Stream, P: PushByte, N, M>(
-    stream: &mut S,
-    builder: &mut P,
-    next: N,
-    mapper: M,
-) -> CompileResult
-where
-    N: Fn(&mut S, &mut P) -> CompileResult,
-    M: Fn(&Token) -> Option<u8>,
-{
-    next(stream, builder)?;
-    if let Some(token_and_pos) = stream.peek() {
-        if let Some(opcode) = mapper(&token_and_pos.token) {
-            let _pos = token_and_pos.pos.clone();
-            stream.next();
-            next(stream, builder)?;
-            builder.push_byte(opcode);
+fn function<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    name: &str,
+    name_pos: Pos,
+    parameters: &[Box<str>],
+    body: &Node,
+    pos: Pos,
+) -> CompileResult {
+    let skip = context.emit_jump(JMP, name_pos.clone());
+    let address = context.offset;
+    context.functions.push(Function {
+        name: name.into(),
+        address,
+        arity: parameters.len() as u8,
+    });
+    let arity = parameters.len();
+    let locals = context.locals.replace(parameters.to_vec());
+    let depth = core::mem::replace(&mut context.depth, 0);
+    let finally = core::mem::replace(&mut context.finally, 0);
+    context.emit(LCL, name_pos.clone());
+    let count = context.offset;
+    context.push_byte(0);
+    generate(context, body)?;
+    context.emit(RET, name_pos.clone());
+    let extra = context
+        .locals
+        .as_ref()
+        .map_or(0, |locals| locals.len() - arity);
+    context.builder.patch_byte(count, extra as u8);
+    context.locals = locals;
+    context.depth = depth;
+    context.finally = finally;
+    context.bodies.push(address..context.offset);
+    context.patch_jump(skip);
+    context.emit(LDV, pos.start..name_pos.end);
+    Ok(())
+}
+
+fn early_return<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    opcode: u8,
+    pos: Pos,
+) -> CompileResult {
+    let name = if opcode == RET { "return" } else { "?" };
+    if context.locals.is_none() {
+        return Err(CompileError {
+            message: format!("Unable to use '{name}' outside of a function body.").into(),
+            pos,
+        });
+    }
+    if context.finally != 0 {
+        return Err(CompileError {
+            message: format!("Unable to use '{name}' inside a 'try' with a 'finally' block.")
+                .into(),
+            pos,
+        });
+    }
+    context.emit(opcode, pos);
+    Ok(())
+}
+
+fn try_catch<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    body: &Node,
+    catch: Option<&Catch>,
+    finally: Option<&Node>,
+    pos: Pos,
+) -> CompileResult {
+    let depth = context.depth;
+    let guarded = finally.is_some() as usize;
+    context.finally += guarded;
+    let start = context.offset;
+    generate(context, body)?;
+    let body_end = context.offset;
+    let mut ends = vec![context.emit_jump(JMP, pos.clone())];
+    let mut handlers = Vec::new();
+    if let Some(catch) = catch {
+        let variable = define(context, &catch.name, catch.name_pos.clone())?;
+        let catch_start = context.offset;
+        handlers.push((start, body_end, catch_start));
+        context.depth = depth + 1;
+        store(context, variable, catch.name_pos.clone());
+        context.emit(POP, catch.name_pos.clone());
+        generate(context, &catch.body)?;
+        let catch_end = context.offset;
+        ends.push(context.emit_jump(JMP, pos.clone()));
+        if finally.is_some() {
+            handlers.push((catch_start, catch_end, context.offset));
+        }
+    } else {
+        handlers.push((start, body_end, context.offset));
+    }
+    context.finally -= guarded;
+    if let Some(finally) = finally {
+        context.depth = depth + 1;
+        context.emit(LDT, pos.clone());
+        let rethrow = context.emit_jump(JMP, pos.clone());
+        context.depth = depth + 1;
+        for address in ends.drain(..) {
+            context.patch_jump(address);
+        }
+        context.emit(LDF, pos.clone());
+        context.patch_jump(rethrow);
+        generate(context, finally)?;
+        context.emit(POP, pos.clone());
+        let skip = context.emit_jump(JFP, pos.clone());
+        context.emit(THR, pos.clone());
+        context.patch_jump(skip);
+        context.depth = depth + 2;
+        context.emit(POP, pos);
+    }
+    for address in ends {
+        context.patch_jump(address);
+    }
+    for (start, end, target) in handlers {
+        for range in handler::ranges(start, end, &context.bodies) {
+            context.handlers.push(Handler {
+                start: range.start,
+                end: range.end,
+                target,
+                depth,
+            });
         }
     }
+    context.depth = depth + 1;
     Ok(())
 }
 
-fn factor<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    multiple_binary_helper(stream, builder, primary, |token| match token {
-        Token::Single(b'*') => Some(MUL),
-        Token::Single(b'/') => Some(DIV),
-        Token::Single(b'%') => Some(MOD),
-        _ => None,
-    })
+fn big_integer<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    value: &BigInt,
+    pos: Pos,
+) -> CompileResult {
+    let (negative, magnitude) = value.parts();
+    if magnitude.len() > u16::MAX as usize {
+        return Err(CompileError {
+            message: "Integer literal is too big.".into(),
+            pos,
+        });
+    }
+    context.emit(LDB, pos);
+    context.push_data(negative as u8);
+    context.push_data(magnitude.len() as u16);
+    for &limb in magnitude {
+        context.push_data(limb);
+    }
+    Ok(())
 }
 
-fn term<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    multiple_binary_helper(stream, builder, factor, |token| match token {
-        Token::Single(b'+') => Some(ADD),
-        Token::Single(b'-') => Some(SUB),
-        _ => None,
-    })
+fn integer<P: PushByte + PatchByte>(context: &mut Context<P>, value: i64, pos: Pos) {
+    match value {
+        0 => context.emit(LD0, pos),
+        1 => context.emit(LD1, pos),
+        _ => {
+            if let Ok(value) = i8::try_from(value) {
+                context.emit(LDI8, pos);
+                context.push_data(value);
+            } else if let Ok(value) = i16::try_from(value) {
+                context.emit(LDI16, pos);
+                context.push_data(value);
+            } else if let Ok(value) = i32::try_from(value) {
+                context.emit(LDI32, pos);
+                context.push_data(value);
+            } else {
+                context.emit(LDI, pos);
+                context.push_data(value);
+            }
+        }
+    }
 }
 
-fn shifts<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    multiple_binary_helper(stream, builder, term, |token| match token {
-        Token::Double(b'<', b'<') => Some(SHL),
-        Token::Double(b'>', b'>') => Some(SHR),
-        _ => None,
-    })
+fn real<P: PushByte + PatchByte>(context: &mut Context<P>, value: f64, pos: Pos) {
+    let index = context.constants.as_mut().and_then(|constants| {
+        match constants
+            .iter()
+            .position(|constant| constant.to_bits() == value.to_bits())
+        {
+            Some(index) => Some(index),
+            None if constants.len() <= u16::MAX as usize => {
+                constants.push(value);
+                Some(constants.len() - 1)
+            }
+            None => None,
+        }
+    });
+    match index {
+        Some(index) => {
+            context.emit(LDC, pos);
+            context.push_data(index as u16);
+        }
+        None => {
+            context.emit(LDR, pos);
+            context.push_data(value);
+        }
+    }
 }
 
-fn and<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    multiple_binary_helper(stream, builder, shifts, |token| match token {
-        Token::Single(b'&') => Some(AND),
-        _ => None,
-    })
+fn field<P: PushByte + PatchByte>(context: &mut Context<P>, name: &str, pos: Pos) -> CompileResult {
+    if name.len() > u16::MAX as usize {
+        return Err(CompileError {
+            message: "Field name is too long.".into(),
+            pos,
+        });
+    }
+    context.emit(FLD, pos);
+    context.push_data(name.len() as u16);
+    for &byte in name.as_bytes() {
+        context.push_byte(byte);
+    }
+    Ok(())
 }
 
-fn xor<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    multiple_binary_helper(stream, builder, and, |token| match token {
-        Token::Single(b'^') => Some(XOR),
-        _ => None,
-    })
+fn postfix<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    node: &Node,
+    skips: &mut Vec<usize>,
+) -> CompileResult {
+    match &node.kind {
+        Kind::Field {
+            object,
+            name,
+            access_pos,
+            safe,
+        } => {
+            postfix(context, object, skips)?;
+            if *safe {
+                skips.push(context.emit_jump(JIN, access_pos.clone()));
+            }
+            field(context, name, access_pos.clone())
+        }
+        Kind::Method {
+            receiver,
+            name,
+            arguments,
+            access_pos,
+            safe,
+        } => {
+            postfix(context, receiver, skips)?;
+            if *safe {
+                skips.push(context.emit_jump(JIN, access_pos.clone()));
+            }
+            let pos = access_pos.start..node.pos.end;
+            call_native(context, name, access_pos.clone(), pos, arguments, 1)
+        }
+        Kind::Unwrap {
+            operand,
+            operator_pos,
+        } => {
+            postfix(context, operand, skips)?;
+            early_return(context, UNW, operator_pos.clone())
+        }
+        _ => generate(context, node),
+    }
 }
 
-fn or<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    multiple_binary_helper(stream, builder, xor, |token| match token {
-        Token::Single(b'|') => Some(OR),
-        _ => None,
-    })
+pub(crate) fn binary_opcode(operator: BinaryOperator) -> u8 {
+    match operator {
+        BinaryOperator::Add => ADD,
+        BinaryOperator::Subtract => SUB,
+        BinaryOperator::Multiply => MUL,
+        BinaryOperator::Divide => DIV,
+        BinaryOperator::Modulo => MOD,
+        BinaryOperator::FloorDivide => FDV,
+        BinaryOperator::EuclidModulo => EMD,
+        BinaryOperator::Power => POW,
+        BinaryOperator::And => AND,
+        BinaryOperator::Or => OR,
+        BinaryOperator::Xor => XOR,
+        BinaryOperator::ShiftLeft => SHL,
+        BinaryOperator::ShiftRight => SHR,
+    }
+}
+
+pub(crate) fn comparison_opcode(operator: ComparisonOperator) -> u8 {
+    match operator {
+        ComparisonOperator::Less => LS,
+        ComparisonOperator::Greater => GR,
+        ComparisonOperator::LessEqual => LE,
+        ComparisonOperator::GreaterEqual => GE,
+        ComparisonOperator::Equal => EQ,
+        ComparisonOperator::NotEqual => NE,
+    }
+}
+
+fn comparison<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    first: &Node,
+    rest: &[(ComparisonOperator, Pos, Node)],
+) -> CompileResult {
+    generate(context, first)?;
+    let mut cleanups = Vec::new();
+    for (index, (operator, pos, operand)) in rest.iter().enumerate() {
+        generate(context, operand)?;
+        let opcode = comparison_opcode(*operator);
+        if index + 1 < rest.len() {
+            context.emit(DUP, pos.clone());
+            context.emit(ROT, pos.clone());
+            context.emit(opcode, pos.clone());
+            cleanups.push((context.emit_jump(JFP, pos.clone()), pos.clone()));
+        } else {
+            context.emit(opcode, pos.clone());
+        }
+    }
+    if let Some((_, pos)) = cleanups.last().cloned() {
+        let end = context.emit_jump(JMP, pos.clone());
+        context.depth += 1;
+        for (address, _) in cleanups {
+            context.patch_jump(address);
+        }
+        context.emit(SWP, pos.clone());
+        context.emit(POP, pos);
+        context.patch_jump(end);
+    }
+    Ok(())
 }
 
-fn comparison<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    single_binary_helper(stream, builder, or, |token| match token {
-        Token::Single(b'<') => Some(LS),
-        Token::Single(b'>') => Some(GR),
-        Token::Double(b'<', b'=') => Some(LE),
-        Token::Double(b'>', b'=') => Some(GE),
-        Token::Double(b'=', b'=') => Some(EQ),
-        Token::Double(b'!', b'=') => Some(NE),
-        _ => None,
+fn coalesce<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    node: &Node,
+    ends: &mut Vec<usize>,
+) -> CompileResult {
+    match &node.kind {
+        Kind::Coalesce {
+            operator_pos,
+            left,
+            right,
+        } => {
+            coalesce(context, left, ends)?;
+            ends.push(context.emit_jump(JNN, operator_pos.clone()));
+            generate(context, right)
+        }
+        _ => generate(context, node),
+    }
+}
+
+fn conditional<P: PushByte + PatchByte>(
+    context: &mut Context<P>,
+    condition: &Node,
+    question_pos: Pos,
+    then: &Node,
+    colon_pos: Pos,
+    otherwise: &Node,
+) -> CompileResult {
+    generate(context, condition)?;
+    let depth = context.depth;
+    let skip = context.emit_jump(JFP, question_pos);
+    generate(context, then)?;
+    let end = context.emit_jump(JMP, colon_pos.clone());
+    context.depth = depth;
+    context.patch_jump(skip);
+    context.emit(POP, colon_pos);
+    generate(context, otherwise)?;
+    context.patch_jump(end);
+    Ok(())
+}
+
+fn generate<P: PushByte + PatchByte>(context: &mut Context<P>, node: &Node) -> CompileResult {
+    let pos = node.pos.clone();
+    match &node.kind {
+        Kind::Void => context.emit(LDV, pos),
+        Kind::Nil => context.emit(LDN, pos),
+        Kind::Boolean(true) => context.emit(LDT, pos),
+        Kind::Boolean(false) => context.emit(LDF, pos),
+        Kind::Yield => context.emit(YLD, pos),
+        Kind::Integer(value) => integer(context, *value, pos),
+        Kind::Real(value) => real(context, *value, pos),
+        Kind::BigInteger(value) => return big_integer(context, value, pos),
+        Kind::Variable(name) => return variable(context, name, pos),
+        Kind::Assign {
+            name,
+            name_pos,
+            value,
+        } => {
+            generate(context, value)?;
+            let variable = define(context, name, name_pos.clone())?;
+            store(context, variable, name_pos.clone());
+        }
+        Kind::Call { name, arguments } => {
+            return match context.functions.iter().rposition(|f| &f.name == name) {
+                Some(index) => call_function(context, index, arguments, pos),
+                None => {
+                    let name_pos = pos.start..pos.start + name.len();
+                    call_native(context, name, name_pos, pos, arguments, 0)
+                }
+            }
+        }
+        Kind::Field { .. } | Kind::Method { .. } | Kind::Unwrap { .. } => {
+            let mut skips = Vec::new();
+            postfix(context, node, &mut skips)?;
+            for address in skips {
+                context.patch_jump(address);
+            }
+        }
+        Kind::Negate {
+            operand,
+            operator_pos,
+        } => {
+            generate(context, operand)?;
+            context.emit(NEG, operator_pos.clone());
+        }
+        Kind::Binary {
+            operator,
+            operator_pos,
+            left,
+            right,
+        } => {
+            generate(context, left)?;
+            generate(context, right)?;
+            context.emit(binary_opcode(*operator), operator_pos.clone());
+        }
+        Kind::Comparison { first, rest } => return comparison(context, first, rest),
+        Kind::Coalesce { .. } => {
+            let mut ends = Vec::new();
+            coalesce(context, node, &mut ends)?;
+            for address in ends {
+                context.patch_jump(address);
+            }
+        }
+        Kind::Conditional {
+            condition,
+            question_pos,
+            then,
+            colon_pos,
+            otherwise,
+        } => {
+            return conditional(
+                context,
+                condition,
+                question_pos.clone(),
+                then,
+                colon_pos.clone(),
+                otherwise,
+            )
+        }
+        Kind::Sequence(nodes) => {
+            for (index, node) in nodes.iter().enumerate() {
+                if index > 0 {
+                    context.emit(POP, nodes[index - 1].pos.clone());
+                }
+                generate(context, node)?;
+            }
+        }
+        Kind::Result { ok, value } => {
+            generate(context, value)?;
+            context.emit(if *ok { MKO } else { MKE }, pos);
+        }
+        Kind::Throw(value) => {
+            generate(context, value)?;
+            context.emit(THR, pos);
+        }
+        Kind::Return(value) => {
+            generate(context, value)?;
+            return early_return(context, RET, pos);
+        }
+        Kind::Try {
+            keyword_pos,
+            body,
+            catch,
+            finally,
+        } => {
+            return try_catch(
+                context,
+                body,
+                catch.as_ref(),
+                finally.as_deref(),
+                keyword_pos.clone(),
+            )
+        }
+        Kind::Function {
+            name,
+            name_pos,
+            parameters,
+            body,
+        } => return function(context, name, name_pos.clone(), parameters, body, pos),
+    }
+    Ok(())
+}
+
+fn generate_tables<P: PushByte + PatchByte>(
+    node: Option<&Node>,
+    builder: &mut P,
+    natives: &dyn Natives,
+    globals: &mut dyn Globals,
+    constants: Option<Vec<f64>>,
+) -> Result<Tables, CompileError> {
+    let mut context = Context {
+        builder,
+        natives,
+        globals,
+        lines: LineTable::new(),
+        handlers: HandlerTable::new(),
+        functions: Vec::new(),
+        bodies: Vec::new(),
+        finally: 0,
+        locals: None,
+        constants,
+        offset: 0,
+        depth: 0,
+    };
+
+    if let Some(node) = node {
+        generate(&mut context, node)?;
+    }
+    context.push_byte(END);
+    Ok(Tables {
+        lines: context.lines,
+        handlers: context.handlers,
+        functions: context.functions,
+        constants: context.constants.unwrap_or_default(),
+        warnings: Vec::new(),
     })
 }
 
-fn binary<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    comparison(stream, builder)
+pub fn generate_with<P: PushByte + PatchByte>(
+    node: Option<&Node>,
+    builder: &mut P,
+    natives: &dyn Natives,
+    globals: &mut dyn Globals,
+) -> Result<Tables, CompileError> {
+    generate_tables(node, builder, natives, globals, Some(Vec::new()))
 }
 
-fn expression<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    binary(stream, builder)
+pub fn compile_with<S: Stream, P: PushByte + PatchByte>(
+    stream: &mut S,
+    builder: &mut P,
+    natives: &dyn Natives,
+    globals: &mut dyn Globals,
+) -> Result<Tables, CompileError> {
+    let mut warnings = Vec::new();
+    let node = parser::parse(stream)?.map(|node| fold::fold(node, &mut warnings));
+    let tables = generate_with(node.as_ref(), builder, natives, globals)?;
+    Ok(Tables { warnings, ..tables })
 }
 
 pub fn compile<S: Stream, P: PushByte>(stream: &mut S, builder: &mut P) -> CompileResult {
-    if stream.peek().is_some() {
-        expression(stream, builder)?;
-    }
-
-    match stream.next() {
-        Some(token_and_pos) => match token_and_pos.token {
-            Token::Integer(value) => Err(CompileError {
-                message: format!("Expected end of code, found integer '{value}'.").into(),
-                pos: token_and_pos.pos,
-            }),
-            Token::Real(value) => Err(CompileError {
-                message: format!("Expected end of code, found real '{value}'.").into(),
-                pos: token_and_pos.pos,
-            }),
-            Token::Single(c) => Err(CompileError {
-                message: format!("Expected end of code, found character '{}'.", c as char).into(),
-                pos: token_and_pos.pos,
-            }),
-            Token::Double(c0, c1) => Err(CompileError {
-                message: format!(
-                    "Expected end of code, found token '{}{}'.",
-                    c0 as char, c1 as char
-                )
-                .into(),
-                pos: token_and_pos.pos,
-            }),
-        },
-        None => {
-            builder.push_byte(END);
-            Ok(())
-        }
+    let node = parser::parse(stream)?.map(|node| fold::fold(node, &mut Vec::new()));
+    let mut code = Vec::new();
+    generate_tables(node.as_ref(), &mut code, &(), &mut (), None)?;
+    for byte in code {
+        builder.push_byte(byte);
     }
+    Ok(())
 }
@@ -0,0 +1,289 @@
+use core::{cell::RefCell, hash::Hash};
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    state::{Stack, State, VMError, VMResult},
+    value::Value,
+};
+
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, String>;
+}
+
+fn mismatch<T>(expected: &str, value: &Value) -> Result<T, String> {
+    Err(format!(
+        "expected {expected}, found {} '{value}'",
+        value.kind()
+    ))
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, String> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Void
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Void => Ok(()),
+            value => mismatch("void", &value),
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Boolean(value) => Ok(value),
+            value => mismatch("boolean", &value),
+        }
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Integer(self)
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Integer(value) => Ok(value),
+            Value::BigInt(value) => Err(format!("integer '{value}' does not fit in 64 bits")),
+            value => mismatch("integer", &value),
+        }
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Real(self)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Real(value) => Ok(value),
+            Value::Integer(value) => Ok(value as f64),
+            Value::BigInt(value) => Ok(value.to_f64()),
+            value => mismatch("real", &value),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::String(value) => Ok(value.to_string()),
+            value => mismatch("string", &value),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Value::Nil,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Nil | Value::Void => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue, E: IntoValue> IntoValue for Result<T, E> {
+    fn into_value(self) -> Value {
+        match self {
+            Ok(value) => Value::Ok(value.into_value().into()),
+            Err(error) => Value::Err(error.into_value().into()),
+        }
+    }
+}
+
+impl<T: FromValue, E: FromValue> FromValue for Result<T, E> {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Ok(value) => T::from_value(value.as_ref().clone()).map(Ok),
+            Value::Err(error) => E::from_value(error.as_ref().clone()).map(Err),
+            value => mismatch("result", &value),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        let list = self.into_iter().map(IntoValue::into_value).collect();
+        Value::List(Rc::new(RefCell::new(list)))
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::List(list) => list
+                .borrow()
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    T::from_value(value.clone()).map_err(|e| format!("{e} at list index {i}"))
+                })
+                .collect(),
+            value => mismatch("list", &value),
+        }
+    }
+}
+
+impl<K: IntoValue, V: IntoValue> IntoValue for HashMap<K, V> {
+    fn into_value(self) -> Value {
+        let map = self
+            .into_iter()
+            .map(|(key, value)| (key.into_value(), value.into_value()))
+            .collect();
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+}
+
+impl<K: FromValue + Eq + Hash, V: FromValue> FromValue for HashMap<K, V> {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Map(map) => map
+                .borrow()
+                .iter()
+                .map(|(key, value)| {
+                    let key = K::from_value(key.clone()).map_err(|e| format!("{e} in map key"))?;
+                    let value =
+                        V::from_value(value.clone()).map_err(|e| format!("{e} in map value"))?;
+                    Ok((key, value))
+                })
+                .collect(),
+            value => mismatch("map", &value),
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($n:literal: $($t:ident $i:tt),*) => {
+        impl<$($t: IntoValue),*> IntoValue for ($($t,)*) {
+            fn into_value(self) -> Value {
+                vec![$(self.$i.into_value()),*].into_value()
+            }
+        }
+
+        impl<$($t: FromValue),*> FromValue for ($($t,)*) {
+            fn from_value(value: Value) -> Result<Self, String> {
+                match value {
+                    Value::List(list) if list.borrow().len() == $n => {
+                        let list = list.borrow();
+                        Ok(($(
+                            $t::from_value(list[$i].clone())
+                                .map_err(|e| format!("{e} at tuple index {}", $i))?,
+                        )*))
+                    }
+                    value => mismatch(concat!("list of ", $n, " values"), &value),
+                }
+            }
+        }
+    };
+}
+
+impl_tuple!(1: A 0);
+impl_tuple!(2: A 0, B 1);
+impl_tuple!(3: A 0, B 1, C 2);
+impl_tuple!(4: A 0, B 1, C 2, D 3);
+
+pub trait IntoNative<S, Args> {
+    const ARITY: u8;
+
+    fn call(&self, state: &mut State<S>, name: &str, arguments: &[Value]) -> VMResult<Value>;
+}
+
+macro_rules! impl_into_native {
+    ($n:literal: $($t:ident $i:tt),*) => {
+        impl<S: Stack, F, R, $($t),*> IntoNative<S, ($($t,)*)> for F
+        where
+            F: Fn($($t),*) -> R,
+            R: IntoValue,
+            $($t: FromValue,)*
+        {
+            const ARITY: u8 = $n;
+
+            #[allow(unused_variables)]
+            fn call(&self, state: &mut State<S>, name: &str, arguments: &[Value]) -> VMResult<Value> {
+                if arguments.len() != $n {
+                    return state.error(
+                        format!(
+                            "Native function '{name}' expects {} argument(s), found {}.",
+                            $n,
+                            arguments.len()
+                        ),
+                        VMError::NativeCall,
+                    );
+                }
+                Ok(self($(
+                    match $t::from_value(arguments[$i].clone()) {
+                        Ok(value) => value,
+                        Err(e) => {
+                            return state.error(
+                                format!("Native function '{name}' argument {}: {e}.", $i + 1),
+                                VMError::NativeCall,
+                            )
+                        }
+                    },
+                )*)
+                .into_value())
+            }
+        }
+    };
+}
+
+impl_into_native!(0:);
+impl_into_native!(1: A 0);
+impl_into_native!(2: A 0, B 1);
+impl_into_native!(3: A 0, B 1, C 2);
+impl_into_native!(4: A 0, B 1, C 2, D 3);
+impl_into_native!(5: A 0, B 1, C 2, D 3, E 4);
+impl_into_native!(6: A 0, B 1, C 2, D 3, E 4, G 5);
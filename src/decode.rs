@@ -0,0 +1,243 @@
+use crate::{
+    bigint::BigInt,
+    get::{GetByte, GetData},
+    handler::{Handler, HandlerTable},
+    opcode::*,
+    value::Value,
+    verify::VerifyError,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    End,
+    Integer(i64),
+    Real(f64),
+    Constant(u16),
+    BigInteger(Value),
+    True,
+    False,
+    Void,
+    Nil,
+    Add,
+    Multiply,
+    Subtract,
+    Divide,
+    Module,
+    FloorDivide,
+    EuclidModule,
+    Power,
+    Negate,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+    Pop,
+    Yield,
+    Duplicate,
+    Swap,
+    Rotate,
+    CallNative(u16, u8),
+    LoadGlobal(u16),
+    StoreGlobal(u16),
+    Jump(usize),
+    JumpIfFalse(usize),
+    JumpIfNotNil(usize),
+    JumpIfNil(usize),
+    Field(Box<str>),
+    Throw,
+    Call(usize, u8),
+    Return,
+    LoadLocal(u8),
+    StoreLocal(u8),
+    Reserve(u8),
+    MakeOk,
+    MakeErr,
+    Unwrap,
+    AddImmediate(i64),
+    SubtractImmediate(i64),
+    MultiplyImmediate(i64),
+    CompareJump(u8, usize),
+    IncrementLocal(u8, i64),
+}
+
+pub struct Decoded {
+    pub code: Vec<Instruction>,
+    pub addresses: Vec<usize>,
+    pub handlers: HandlerTable,
+}
+
+fn operand<G, T>(program: &G, address: usize, offset: usize) -> Result<T, VerifyError>
+where
+    G: GetData<T>,
+{
+    program
+        .get_data(address + offset)
+        .ok_or(VerifyError::MissingOperand { address })
+}
+
+fn index(addresses: &[usize], address: usize, target: usize) -> Result<usize, VerifyError> {
+    addresses
+        .binary_search(&target)
+        .map_err(|_| VerifyError::InvalidTarget { address, target })
+}
+
+fn instruction<G: GetByte>(
+    program: &G,
+    addresses: &[usize],
+    address: usize,
+    opcode: u8,
+) -> Result<Instruction, VerifyError> {
+    let target = |offset| {
+        let target: u32 = operand(program, address, offset)?;
+        index(addresses, address, target as usize)
+    };
+    Ok(match opcode {
+        END => Instruction::End,
+        LDI => Instruction::Integer(operand(program, address, 1)?),
+        LD0 => Instruction::Integer(0),
+        LD1 => Instruction::Integer(1),
+        LDI8 => Instruction::Integer(operand::<G, i8>(program, address, 1)? as i64),
+        LDI16 => Instruction::Integer(operand::<G, i16>(program, address, 1)? as i64),
+        LDI32 => Instruction::Integer(operand::<G, i32>(program, address, 1)? as i64),
+        LDR => Instruction::Real(operand(program, address, 1)?),
+        LDC => Instruction::Constant(operand(program, address, 1)?),
+        LDB => {
+            let negative: u8 = operand(program, address, 1)?;
+            let count: u16 = operand(program, address, 2)?;
+            let magnitude = (0..count as usize)
+                .map(|i| operand(program, address, 4 + i * 4))
+                .collect::<Result<Vec<u32>, _>>()?;
+            Instruction::BigInteger(Value::from_big(BigInt::from_parts(
+                negative != 0,
+                magnitude,
+            )))
+        }
+        LDT => Instruction::True,
+        LDF => Instruction::False,
+        LDV => Instruction::Void,
+        LDN => Instruction::Nil,
+        ADD => Instruction::Add,
+        MUL => Instruction::Multiply,
+        SUB => Instruction::Subtract,
+        DIV => Instruction::Divide,
+        MOD => Instruction::Module,
+        FDV => Instruction::FloorDivide,
+        EMD => Instruction::EuclidModule,
+        POW => Instruction::Power,
+        NEG => Instruction::Negate,
+        LS => Instruction::Less,
+        GR => Instruction::Greater,
+        LE => Instruction::LessEqual,
+        GE => Instruction::GreaterEqual,
+        EQ => Instruction::Equal,
+        NE => Instruction::NotEqual,
+        AND => Instruction::And,
+        OR => Instruction::Or,
+        XOR => Instruction::Xor,
+        SHL => Instruction::ShiftLeft,
+        SHR => Instruction::ShiftRight,
+        POP => Instruction::Pop,
+        YLD => Instruction::Yield,
+        DUP => Instruction::Duplicate,
+        SWP => Instruction::Swap,
+        ROT => Instruction::Rotate,
+        CLN => {
+            Instruction::CallNative(operand(program, address, 1)?, operand(program, address, 3)?)
+        }
+        LDG => Instruction::LoadGlobal(operand(program, address, 1)?),
+        STG => Instruction::StoreGlobal(operand(program, address, 1)?),
+        JMP => Instruction::Jump(target(1)?),
+        JFP => Instruction::JumpIfFalse(target(1)?),
+        JNN => Instruction::JumpIfNotNil(target(1)?),
+        JIN => Instruction::JumpIfNil(target(1)?),
+        FLD => {
+            let length: u16 = operand(program, address, 1)?;
+            let bytes = (0..length as usize)
+                .map(|i| operand(program, address, 3 + i))
+                .collect::<Result<Vec<u8>, _>>()?;
+            Instruction::Field(String::from_utf8_lossy(&bytes).into())
+        }
+        THR => Instruction::Throw,
+        CAL => Instruction::Call(target(1)?, operand(program, address, 5)?),
+        RET => Instruction::Return,
+        LDL => Instruction::LoadLocal(operand(program, address, 1)?),
+        STL => Instruction::StoreLocal(operand(program, address, 1)?),
+        LCL => Instruction::Reserve(operand(program, address, 1)?),
+        MKO => Instruction::MakeOk,
+        MKE => Instruction::MakeErr,
+        UNW => Instruction::Unwrap,
+        ADI => Instruction::AddImmediate(operand::<G, i32>(program, address, 1)? as i64),
+        SBI => Instruction::SubtractImmediate(operand::<G, i32>(program, address, 1)? as i64),
+        MLI => Instruction::MultiplyImmediate(operand::<G, i32>(program, address, 1)? as i64),
+        CJP => {
+            let compare: u8 = operand(program, address, 1)?;
+            if !matches!(compare, LS | GR | LE | GE | EQ | NE) {
+                return Err(VerifyError::UnknownOpcode {
+                    address,
+                    opcode: compare,
+                });
+            }
+            Instruction::CompareJump(compare, target(2)?)
+        }
+        INL => Instruction::IncrementLocal(
+            operand(program, address, 1)?,
+            operand::<G, i32>(program, address, 2)? as i64,
+        ),
+        opcode => return Err(VerifyError::UnknownOpcode { address, opcode }),
+    })
+}
+
+pub fn decode<G: GetByte>(program: &G, handlers: &HandlerTable) -> Result<Decoded, VerifyError> {
+    let mut addresses = Vec::new();
+    let mut opcodes = Vec::new();
+    let mut address = 0;
+    while let Some(opcode) = program.get_byte(address) {
+        let info = info(opcode).ok_or(VerifyError::UnknownOpcode { address, opcode })?;
+        let size = info
+            .size(program, address)
+            .ok_or(VerifyError::MissingOperand { address })?;
+        addresses.push(address);
+        opcodes.push(opcode);
+        address += size;
+    }
+    let code = addresses
+        .iter()
+        .zip(opcodes)
+        .map(|(&address, opcode)| instruction(program, &addresses, address, opcode))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut table = HandlerTable::new();
+    for handler in handlers.iter() {
+        if handler.end < handler.start {
+            return Err(VerifyError::InvalidHandler {
+                start: handler.start,
+                end: handler.end,
+            });
+        }
+        table.push(Handler {
+            start: index(&addresses, handler.start, handler.start)?,
+            end: match handler.end {
+                end if end == address => addresses.len(),
+                end => index(&addresses, handler.start, end).map_err(|_| {
+                    VerifyError::InvalidHandler {
+                        start: handler.start,
+                        end,
+                    }
+                })?,
+            },
+            target: index(&addresses, handler.start, handler.target)?,
+            depth: handler.depth,
+        });
+    }
+    Ok(Decoded {
+        code,
+        addresses,
+        handlers: table,
+    })
+}
@@ -0,0 +1,130 @@
+use core::fmt::Write;
+
+use crate::{
+    get::{GetByte, GetData},
+    impls::slice_reader,
+    line::{self, LineTable},
+    opcode::{info, Operand},
+};
+
+fn targets<G: GetByte>(program: &G) -> Vec<usize> {
+    let mut targets = Vec::new();
+    let mut address = 0;
+    while let Some(opcode) = program.get_byte(address) {
+        let info = match info(opcode) {
+            Some(info) => info,
+            None => {
+                address += 1;
+                continue;
+            }
+        };
+        let mut offset = address + 1;
+        for operand in info.operands {
+            if *operand == Operand::Address {
+                if let Some(target) = GetData::<u32>::get_data(program, offset) {
+                    targets.push(target as usize);
+                }
+            }
+            offset += operand.size(program, offset).unwrap_or(1);
+        }
+        address = offset;
+    }
+    targets.sort_unstable();
+    targets.dedup();
+    targets
+}
+
+fn operand<G: GetByte>(program: &G, operand: Operand, address: usize) -> Option<String> {
+    Some(match operand {
+        Operand::Byte => GetData::<u8>::get_data(program, address)?.to_string(),
+        Operand::Opcode => {
+            let opcode: u8 = program.get_data(address)?;
+            match info(opcode) {
+                Some(info) => info.name.to_string(),
+                None => format!("{opcode:#04X}"),
+            }
+        }
+        Operand::Index => GetData::<u16>::get_data(program, address)?.to_string(),
+        Operand::Address => format!("L{:04X}", GetData::<u32>::get_data(program, address)?),
+        Operand::Int8 => GetData::<i8>::get_data(program, address)?.to_string(),
+        Operand::Int16 => GetData::<i16>::get_data(program, address)?.to_string(),
+        Operand::Int32 => GetData::<i32>::get_data(program, address)?.to_string(),
+        Operand::Integer => GetData::<i64>::get_data(program, address)?.to_string(),
+        Operand::Real => format!("{:?}", GetData::<f64>::get_data(program, address)?),
+        Operand::Limbs => {
+            let count: u16 = program.get_data(address)?;
+            let limbs = (0..count as usize)
+                .map(|i| {
+                    GetData::<u32>::get_data(program, address + 2 + i * 4)
+                        .map(|limb| format!("{limb:#X}"))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            format!("[{}]", limbs.join(", "))
+        }
+        Operand::Name => {
+            let length: u16 = program.get_data(address)?;
+            let bytes = (0..length as usize)
+                .map(|i| program.get_byte(address + 2 + i))
+                .collect::<Option<Vec<_>>>()?;
+            format!("{:?}", String::from_utf8_lossy(&bytes))
+        }
+    })
+}
+
+fn source_line(source: &str, start: usize) -> String {
+    let info = line::create(slice_reader::new(source.as_bytes()), start);
+    let text = source[info.start..].lines().next().unwrap_or_default();
+    format!("line {}: {}", info.number, text.trim())
+}
+
+pub fn disassemble<G: GetByte>(
+    program: &G,
+    lines: Option<&LineTable>,
+    source: Option<&str>,
+) -> String {
+    let targets = targets(program);
+    let mut output = String::new();
+    let mut last = None;
+    let mut address = 0;
+    while let Some(opcode) = program.get_byte(address) {
+        if targets.binary_search(&address).is_ok() {
+            writeln!(output, "L{address:04X}:").unwrap();
+        }
+        let info = match info(opcode) {
+            Some(info) => info,
+            None => {
+                writeln!(output, "    ; {address:04X} unknown opcode {opcode:#04X}").unwrap();
+                address += 1;
+                continue;
+            }
+        };
+        let mut text = String::from(info.name);
+        let mut offset = address + 1;
+        for &kind in info.operands {
+            match operand(program, kind, offset) {
+                Some(operand) => write!(text, " {operand}").unwrap(),
+                None => {
+                    write!(text, " <truncated>").unwrap();
+                    break;
+                }
+            }
+            offset += kind.size(program, offset).unwrap_or(1);
+        }
+        write!(output, "    {text:<23} ; {address:04X}").unwrap();
+        if let Some(pos) = lines.and_then(|lines| lines.find(address)) {
+            match source {
+                Some(source) => {
+                    let line = source_line(source, pos.start);
+                    if last.as_ref() != Some(&line) {
+                        write!(output, " {line}").unwrap();
+                        last = Some(line);
+                    }
+                }
+                None => write!(output, " at {}..{}", pos.start, pos.end).unwrap(),
+            }
+        }
+        output.push('\n');
+        address = offset;
+    }
+    output
+}
@@ -0,0 +1,404 @@
+use core::fmt;
+
+use crate::{
+    compiler::{self, CompileError, Function, Globals, Tables, Warning},
+    convert::IntoNative,
+    decode::{self, Decoded},
+    file::{self, Image, LoadError},
+    get::GetByte,
+    handler::HandlerTable,
+    impls::{data_stack, slice_reader, static_data, token_stream},
+    line::LineTable,
+    native::{Native, Natives},
+    opcode, peephole,
+    state::{Arithmetic, Stack, State, VMError, VMResult},
+    token::Pos,
+    value::Value,
+    verify::VerifyError,
+    vm::{self, Status},
+};
+
+pub struct RuntimeError {
+    pub error: VMError,
+    pub message: Option<Box<str>>,
+    pub pos: Option<Pos>,
+    pub backtrace: Vec<Pos>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{message}"),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+pub enum Error {
+    Compile(CompileError),
+    Runtime(RuntimeError),
+    Verify(VerifyError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Compile(error) => write!(f, "Compile error: {}", error.message),
+            Error::Runtime(error) => write!(f, "Runtime error: {error}"),
+            Error::Verify(error) => write!(f, "Invalid bytecode: {error}"),
+        }
+    }
+}
+
+impl From<CompileError> for Error {
+    fn from(value: CompileError) -> Self {
+        Error::Compile(value)
+    }
+}
+
+pub enum SaveError {
+    Unknown,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Unknown => write!(
+                f,
+                "Program uses a native function or global variable unknown to this engine."
+            ),
+        }
+    }
+}
+
+pub struct Program {
+    code: Box<[u8]>,
+    lines: LineTable,
+    handlers: HandlerTable,
+    functions: Vec<Function>,
+    constants: Vec<f64>,
+    warnings: Vec<Warning>,
+    decoded: Decoded,
+}
+
+impl Program {
+    pub fn lines(&self) -> &LineTable {
+        &self.lines
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+}
+
+impl GetByte for Program {
+    fn get_byte(&self, address: usize) -> Option<u8> {
+        self.code.get(address).cloned()
+    }
+}
+
+pub(crate) struct Names(pub(crate) Vec<Box<str>>);
+
+impl Globals for Names {
+    fn find(&self, name: &str) -> Option<u16> {
+        self.0
+            .iter()
+            .position(|n| n.as_ref() == name)
+            .map(|index| index as u16)
+    }
+
+    fn define(&mut self, name: &str) -> Option<u16> {
+        match self.find(name) {
+            Some(index) => Some(index),
+            None if self.0.len() <= u16::MAX as usize => {
+                self.0.push(name.into());
+                Some((self.0.len() - 1) as u16)
+            }
+            None => None,
+        }
+    }
+}
+
+fn remap(
+    code: &mut Vec<u8>,
+    global: &mut dyn FnMut(u16) -> Option<u16>,
+    native: &mut dyn FnMut(u16) -> Option<u16>,
+) -> Option<()> {
+    let mut address = 0;
+    while let Some(&opcode) = code.get(address) {
+        let size = opcode::info(opcode)?.size(code, address)?;
+        let map: &mut dyn FnMut(u16) -> Option<u16> = match opcode {
+            opcode::LDG | opcode::STG => global,
+            opcode::CLN => native,
+            _ => {
+                address += size;
+                continue;
+            }
+        };
+        let operand = &mut code[address + 1..address + 3];
+        let index = map(u16::from_be_bytes([operand[0], operand[1]]))?;
+        operand.copy_from_slice(&index.to_be_bytes());
+        address += size;
+    }
+    Some(())
+}
+
+pub(crate) fn builtins<S: Stack>(state: &mut State<S>) {
+    state
+        .natives
+        .register("pop", 1, |state, arguments| match &arguments[0] {
+            Value::List(list) => Ok(list.borrow_mut().pop().unwrap_or(Value::Nil)),
+            value => state.error(
+                format!(
+                    "Native function 'pop' argument 1: expected list, found {} '{value}'.",
+                    value.kind()
+                ),
+                VMError::NativeCall,
+            ),
+        });
+}
+
+pub struct Engine<S> {
+    state: State<S>,
+    names: Names,
+    yielded: bool,
+}
+
+impl<S: Stack> Engine<S> {
+    pub fn with_stack(stack: S) -> Self {
+        let mut state = State::new(stack);
+        builtins(&mut state);
+        Self {
+            state,
+            names: Names(Vec::new()),
+            yielded: false,
+        }
+    }
+
+    pub fn register<F>(&mut self, name: &str, arity: u8, function: F) -> Native
+    where
+        F: Fn(&mut State<S>, &[Value]) -> VMResult<Value> + 'static,
+    {
+        self.state.natives.register(name, arity, function)
+    }
+
+    pub fn register_fn<Args, F>(&mut self, name: &str, function: F) -> Native
+    where
+        F: IntoNative<S, Args> + 'static,
+    {
+        self.state.natives.register_fn(name, function)
+    }
+
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.state.arithmetic = arithmetic;
+    }
+
+    pub fn global(&self, name: &str) -> Option<Value> {
+        let index = self.names.find(name)?;
+        self.state.globals.get(index as usize).cloned()
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        if let Some(index) = self.names.define(name) {
+            let index = index as usize;
+            if index >= self.state.globals.len() {
+                self.state.globals.resize(index + 1, Value::Void);
+            }
+            self.state.globals[index] = value;
+        }
+    }
+
+    pub fn compile(&mut self, source: &str) -> Result<Program, Error> {
+        let mut stream = token_stream::new(slice_reader::new(source.as_bytes()));
+        let mut code = Vec::new();
+        let count = self.names.0.len();
+        let result =
+            compiler::compile_with(&mut stream, &mut code, &self.state.natives, &mut self.names);
+        let (
+            code,
+            Tables {
+                lines,
+                handlers,
+                functions,
+                constants,
+                warnings,
+            },
+        ) = match result {
+            Ok(tables) => peephole::optimize(code, tables),
+            Err(error) => {
+                self.names.0.truncate(count);
+                return Err(error.into());
+            }
+        };
+        let decoded = decode::decode(&code, &handlers).map_err(|error| {
+            self.names.0.truncate(count);
+            Error::Verify(error)
+        })?;
+        Ok(Program {
+            code: code.into_boxed_slice(),
+            lines,
+            handlers,
+            functions,
+            constants,
+            warnings,
+            decoded,
+        })
+    }
+
+    pub fn save(&self, program: &Program, debug: bool) -> Result<Vec<u8>, SaveError> {
+        let mut natives: Vec<(Box<str>, u8)> = Vec::new();
+        let mut globals: Vec<Box<str>> = Vec::new();
+        let mut native = |index: u16| {
+            let name = self.state.natives.name(index)?;
+            let (arity, _) = self.state.natives.get(index)?;
+            let position = natives.iter().position(|(n, _)| n.as_ref() == name);
+            Some(position.unwrap_or_else(|| {
+                natives.push((name.into(), arity));
+                natives.len() - 1
+            }) as u16)
+        };
+        let mut global = |index: u16| {
+            let name = self.names.0.get(index as usize)?;
+            let position = globals.iter().position(|n| n == name);
+            Some(position.unwrap_or_else(|| {
+                globals.push(name.clone());
+                globals.len() - 1
+            }) as u16)
+        };
+        let mut code = program.code.to_vec();
+        remap(&mut code, &mut global, &mut native).ok_or(SaveError::Unknown)?;
+        Ok(file::write(&Image {
+            code: code.into_boxed_slice(),
+            natives,
+            globals,
+            constants: program.constants.clone(),
+            functions: program.functions.clone(),
+            handlers: program.handlers.clone(),
+            lines: debug.then(|| program.lines.clone()),
+        }))
+    }
+
+    pub fn load(&mut self, bytes: &[u8]) -> Result<Program, LoadError> {
+        let image = file::read(bytes)?;
+        let mut natives = Vec::with_capacity(image.natives.len());
+        for (name, arity) in &image.natives {
+            match self.state.natives.find(name) {
+                Some(native) if native.arity == *arity => natives.push(native.index),
+                _ => return Err(LoadError::Native(name.clone())),
+            }
+        }
+        let count = self.names.0.len();
+        let mut globals = Vec::with_capacity(image.globals.len());
+        for name in &image.globals {
+            match self.names.define(name) {
+                Some(index) => globals.push(index),
+                None => {
+                    self.names.0.truncate(count);
+                    return Err(LoadError::Global(name.clone()));
+                }
+            }
+        }
+        let mut code = image.code.to_vec();
+        let remapped = remap(
+            &mut code,
+            &mut |index| globals.get(index as usize).copied(),
+            &mut |index| natives.get(index as usize).copied(),
+        );
+        let decoded = match remapped.map(|_| decode::decode(&code, &image.handlers)) {
+            Some(Ok(decoded)) => decoded,
+            Some(Err(error)) => {
+                self.names.0.truncate(count);
+                return Err(LoadError::Verify(error));
+            }
+            None => {
+                self.names.0.truncate(count);
+                return Err(LoadError::Corrupted);
+            }
+        };
+        Ok(Program {
+            code: code.into_boxed_slice(),
+            lines: image.lines.unwrap_or_default(),
+            handlers: image.handlers,
+            functions: image.functions,
+            constants: image.constants,
+            warnings: Vec::new(),
+            decoded,
+        })
+    }
+
+    fn runtime_error(&mut self, program: &Program, error: VMError) -> Error {
+        let decoded = &program.decoded;
+        let find = |index: usize| {
+            let address = decoded.addresses.get(index)?;
+            program.lines.find(*address)
+        };
+        Error::Runtime(RuntimeError {
+            error,
+            message: self.state.message.take(),
+            pos: find(self.state.program_counter),
+            backtrace: self
+                .state
+                .frames
+                .iter()
+                .rev()
+                .filter_map(|frame| find(frame.return_address - 1))
+                .collect(),
+        })
+    }
+
+    pub fn start(&mut self, program: &Program) {
+        self.state.reset();
+        self.state.constants.clone_from(&program.constants);
+        self.yielded = false;
+    }
+
+    pub fn resume(&mut self, program: &Program, mut steps: usize) -> Result<Status, Error> {
+        let decoded = &program.decoded;
+        loop {
+            let error = match vm::execute_for(&mut self.state, decoded, &mut steps) {
+                Ok(status) => {
+                    self.yielded = matches!(status, Status::Yielded);
+                    return Ok(status);
+                }
+                Err(error) => error,
+            };
+            if let Err(error) = vm::unwind(&mut self.state, &decoded.handlers, error) {
+                self.yielded = false;
+                return Err(self.runtime_error(program, error));
+            }
+        }
+    }
+
+    pub fn resume_with(
+        &mut self,
+        program: &Program,
+        value: Value,
+        steps: usize,
+    ) -> Result<Status, Error> {
+        if self.yielded {
+            let result = self.state.drop().and_then(|_| self.state.push(value));
+            if let Err(error) = result {
+                return Err(self.runtime_error(program, error));
+            }
+        }
+        self.resume(program, steps)
+    }
+
+    pub fn run(&mut self, program: &Program) -> Result<Value, Error> {
+        self.start(program);
+        loop {
+            if let Status::Finished(value) = self.resume(program, usize::MAX)? {
+                return Ok(value);
+            }
+        }
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let program = self.compile(source)?;
+        self.run(&program)
+    }
+}
+
+pub fn new() -> Engine<impl Stack> {
+    Engine::with_stack(data_stack::new(static_data::new::<256>()))
+}
@@ -0,0 +1,284 @@
+use core::fmt;
+
+use crate::{
+    compiler::Function,
+    get::{GetByte, GetData},
+    handler::{Handler, HandlerTable},
+    line::LineTable,
+    opcode,
+    push::PushData,
+    verify::{self, VerifyError},
+};
+
+pub const MAGIC: [u8; 4] = *b"ARIA";
+pub const VERSION: u16 = 2;
+
+const HEADER: usize = 12;
+const CHECKSUM: usize = 4;
+
+pub enum LoadError {
+    Truncated,
+    Magic,
+    Version(u16),
+    OpcodeVersion(u16),
+    Checksum,
+    Corrupted,
+    Native(Box<str>),
+    Global(Box<str>),
+    Verify(VerifyError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Truncated => write!(f, "Bytecode file is truncated."),
+            LoadError::Magic => write!(f, "Not an Aria bytecode file."),
+            LoadError::Version(version) => write!(
+                f,
+                "Unsupported bytecode file version {version}, expected {VERSION}."
+            ),
+            LoadError::OpcodeVersion(version) => write!(
+                f,
+                "Unsupported opcode set version {version}, expected {}.",
+                opcode::VERSION
+            ),
+            LoadError::Checksum => write!(f, "Bytecode file checksum mismatch."),
+            LoadError::Corrupted => write!(f, "Bytecode file is corrupted."),
+            LoadError::Native(name) => write!(f, "Unknown native function '{name}'."),
+            LoadError::Global(name) => write!(f, "Unable to bind global variable '{name}'."),
+            LoadError::Verify(error) => write!(f, "Invalid bytecode: {error}"),
+        }
+    }
+}
+
+pub struct Image {
+    pub code: Box<[u8]>,
+    pub natives: Vec<(Box<str>, u8)>,
+    pub globals: Vec<Box<str>>,
+    pub constants: Vec<f64>,
+    pub functions: Vec<Function>,
+    pub handlers: HandlerTable,
+    pub lines: Option<LineTable>,
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811C9DC5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+fn push_str(output: &mut Vec<u8>, value: &str) {
+    output.push_data(value.len() as u16);
+    output.extend_from_slice(value.as_bytes());
+}
+
+pub fn write(image: &Image) -> Vec<u8> {
+    let mut output = Vec::new();
+    output.extend_from_slice(&MAGIC);
+    output.push_data(VERSION);
+    output.push_data(opcode::VERSION);
+    output.push_data(0u32);
+
+    output.push_data(image.natives.len() as u16);
+    for (name, arity) in &image.natives {
+        push_str(&mut output, name);
+        output.push_data(*arity);
+    }
+    output.push_data(image.globals.len() as u16);
+    for name in &image.globals {
+        push_str(&mut output, name);
+    }
+    output.push_data(image.constants.len() as u16);
+    for &constant in &image.constants {
+        output.push_data(constant);
+    }
+
+    output.push_data(image.functions.len() as u32);
+    for function in &image.functions {
+        push_str(&mut output, &function.name);
+        output.push_data(function.address as u32);
+        output.push_data(function.arity);
+    }
+
+    let handlers: Vec<&Handler> = image.handlers.iter().collect();
+    output.push_data(handlers.len() as u32);
+    for handler in handlers {
+        output.push_data(handler.start as u32);
+        output.push_data(handler.end as u32);
+        output.push_data(handler.target as u32);
+        output.push_data(handler.depth as u32);
+    }
+
+    match &image.lines {
+        Some(lines) => {
+            let lines: Vec<_> = lines.iter().collect();
+            output.push_data(1u8);
+            output.push_data(lines.len() as u32);
+            for (address, pos) in lines {
+                output.push_data(*address as u32);
+                output.push_data(pos.start as u32);
+                output.push_data(pos.end as u32);
+            }
+        }
+        None => output.push_data(0u8),
+    }
+
+    output.push_data(image.code.len() as u32);
+    output.extend_from_slice(&image.code);
+
+    let length = (output.len() + CHECKSUM) as u32;
+    output[8..HEADER].copy_from_slice(&length.to_be_bytes());
+    output.push_data(checksum(&output));
+    output
+}
+
+struct Input<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl GetByte for Input<'_> {
+    fn get_byte(&self, address: usize) -> Option<u8> {
+        self.bytes.get(address).cloned()
+    }
+}
+
+impl<'a> Input<'a> {
+    fn read<T>(&mut self) -> Result<T, LoadError>
+    where
+        Self: GetData<T>,
+    {
+        let value = self.get_data(self.offset).ok_or(LoadError::Truncated)?;
+        self.offset += core::mem::size_of::<T>();
+        Ok(value)
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], LoadError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + length)
+            .ok_or(LoadError::Truncated)?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn read_str(&mut self) -> Result<Box<str>, LoadError> {
+        let length: u16 = self.read()?;
+        let bytes = self.read_bytes(length as usize)?;
+        core::str::from_utf8(bytes)
+            .map(Into::into)
+            .map_err(|_| LoadError::Corrupted)
+    }
+
+    fn read_usize(&mut self) -> Result<usize, LoadError> {
+        self.read::<u32>().map(|value| value as usize)
+    }
+}
+
+pub fn read(bytes: &[u8]) -> Result<Image, LoadError> {
+    if bytes.len() < MAGIC.len() {
+        return Err(LoadError::Truncated);
+    }
+    if bytes[..MAGIC.len()] != MAGIC {
+        return Err(LoadError::Magic);
+    }
+    let mut input = Input {
+        bytes,
+        offset: MAGIC.len(),
+    };
+    let version: u16 = input.read()?;
+    if version != VERSION {
+        return Err(LoadError::Version(version));
+    }
+    let opcode_version: u16 = input.read()?;
+    if opcode_version != opcode::VERSION {
+        return Err(LoadError::OpcodeVersion(opcode_version));
+    }
+    let length = input.read_usize()?;
+    if bytes.len() < length {
+        return Err(LoadError::Truncated);
+    }
+    if bytes.len() > length || length < HEADER + CHECKSUM {
+        return Err(LoadError::Corrupted);
+    }
+    let (body, expected) = bytes.split_at(length - CHECKSUM);
+    if checksum(body) != u32::from_be_bytes([expected[0], expected[1], expected[2], expected[3]]) {
+        return Err(LoadError::Checksum);
+    }
+    input.bytes = body;
+
+    let count: u16 = input.read()?;
+    let mut natives = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name = input.read_str()?;
+        natives.push((name, input.read()?));
+    }
+    let count: u16 = input.read()?;
+    let mut globals = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        globals.push(input.read_str()?);
+    }
+    let count: u16 = input.read()?;
+    let mut constants = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        constants.push(input.read()?);
+    }
+
+    let count = input.read_usize()?;
+    let mut functions = Vec::new();
+    for _ in 0..count {
+        functions.push(Function {
+            name: input.read_str()?,
+            address: input.read_usize()?,
+            arity: input.read()?,
+        });
+    }
+
+    let count = input.read_usize()?;
+    let mut handlers = HandlerTable::new();
+    for _ in 0..count {
+        handlers.push(Handler {
+            start: input.read_usize()?,
+            end: input.read_usize()?,
+            target: input.read_usize()?,
+            depth: input.read_usize()?,
+        });
+    }
+
+    let lines = match input.read::<u8>()? {
+        0 => None,
+        1 => {
+            let count = input.read_usize()?;
+            let mut lines = LineTable::new();
+            for _ in 0..count {
+                let address = input.read_usize()?;
+                let start = input.read_usize()?;
+                lines.push(address, start..input.read_usize()?);
+            }
+            Some(lines)
+        }
+        _ => return Err(LoadError::Corrupted),
+    };
+
+    let length = input.read_usize()?;
+    let code: Box<[u8]> = input.read_bytes(length)?.into();
+    if input.offset != body.len() {
+        return Err(LoadError::Corrupted);
+    }
+
+    let program = Input {
+        bytes: &code,
+        offset: 0,
+    };
+    verify::verify(&program, &handlers).map_err(LoadError::Verify)?;
+
+    Ok(Image {
+        code,
+        natives,
+        globals,
+        constants,
+        functions,
+        handlers,
+        lines,
+    })
+}
@@ -0,0 +1,411 @@
+use crate::{
+    ast::*,
+    compiler::Warning,
+    impls::{data_stack, static_data},
+    state::{Arithmetic, Stack, State, VMResult},
+    token::Pos,
+    value::Value,
+};
+
+const MODES: [Arithmetic; 4] = [
+    Arithmetic::Wrapping,
+    Arithmetic::Checked,
+    Arithmetic::Saturating,
+    Arithmetic::Promoting,
+];
+
+const BITS: u64 = 1 << 16;
+
+type Operation<S> = fn(&mut State<S>) -> VMResult<()>;
+
+enum Outcome {
+    Folded(Value),
+    Failed(Box<str>),
+    Unknown,
+}
+
+struct Context<S> {
+    state: State<S>,
+    warnings: Vec<Warning>,
+}
+
+fn constant(node: &Node) -> Option<Value> {
+    match &node.kind {
+        Kind::Nil => Some(Value::Nil),
+        Kind::Boolean(value) => Some(Value::Boolean(*value)),
+        Kind::Integer(value) => Some(Value::Integer(*value)),
+        Kind::Real(value) => Some(Value::Real(*value)),
+        Kind::BigInteger(value) => Some(Value::from_big(value.clone())),
+        _ => None,
+    }
+}
+
+fn literal(value: Value) -> Option<Kind> {
+    match value {
+        Value::Nil => Some(Kind::Nil),
+        Value::Boolean(value) => Some(Kind::Boolean(value)),
+        Value::Integer(value) => Some(Kind::Integer(value)),
+        Value::Real(value) => Some(Kind::Real(value)),
+        Value::BigInt(value) => Some(Kind::BigInteger(value.as_ref().clone())),
+        _ => None,
+    }
+}
+
+fn is_pure(node: &Node) -> bool {
+    matches!(node.kind, Kind::Void) || constant(node).is_some()
+}
+
+fn bits(value: &Value) -> u64 {
+    match value {
+        Value::Integer(value) => 64 - value.unsigned_abs().leading_zeros() as u64,
+        Value::BigInt(value) => value.parts().1.len() as u64 * 32,
+        _ => 0,
+    }
+}
+
+fn is_large(operator: BinaryOperator, left: &Value, right: &Value) -> bool {
+    let Value::Integer(right) = right else {
+        return false;
+    };
+    match operator {
+        BinaryOperator::Power => bits(left).saturating_mul(right.unsigned_abs()) > BITS,
+        BinaryOperator::ShiftLeft => bits(left).saturating_add(right.unsigned_abs()) > BITS,
+        _ => false,
+    }
+}
+
+fn evaluate<S: Stack>(
+    context: &mut Context<S>,
+    operation: Operation<S>,
+    operands: &[Value],
+) -> Outcome {
+    let mut results = Vec::with_capacity(MODES.len());
+    for mode in MODES {
+        let state = &mut context.state;
+        state.reset();
+        state.arithmetic = mode;
+        let result = operands
+            .iter()
+            .try_for_each(|operand| state.push(operand.clone()))
+            .and_then(|_| operation(state))
+            .and_then(|_| state.pop());
+        results.push(result.map_err(|error| match state.message.take() {
+            Some(message) => message,
+            None => error.to_string().into_boxed_str(),
+        }));
+    }
+    match results.first() {
+        Some(Ok(first)) if results.iter().all(|result| result.as_ref() == Ok(first)) => {
+            Outcome::Folded(first.clone())
+        }
+        Some(Err(message)) if results.iter().all(Result::is_err) => {
+            Outcome::Failed(message.clone())
+        }
+        _ => Outcome::Unknown,
+    }
+}
+
+fn binary_operation<S: Stack>(operator: BinaryOperator) -> Operation<S> {
+    match operator {
+        BinaryOperator::Add => State::addict,
+        BinaryOperator::Subtract => State::subtract,
+        BinaryOperator::Multiply => State::multiply,
+        BinaryOperator::Divide => State::divide,
+        BinaryOperator::Modulo => State::module,
+        BinaryOperator::FloorDivide => State::floor_divide,
+        BinaryOperator::EuclidModulo => State::euclid_module,
+        BinaryOperator::Power => State::power,
+        BinaryOperator::And => State::and,
+        BinaryOperator::Or => State::or,
+        BinaryOperator::Xor => State::xor,
+        BinaryOperator::ShiftLeft => State::shift_left,
+        BinaryOperator::ShiftRight => State::shift_right,
+    }
+}
+
+fn comparison_operation<S: Stack>(operator: ComparisonOperator) -> Operation<S> {
+    match operator {
+        ComparisonOperator::Less => State::less,
+        ComparisonOperator::Greater => State::greater,
+        ComparisonOperator::LessEqual => State::less_equals,
+        ComparisonOperator::GreaterEqual => State::greater_equals,
+        ComparisonOperator::Equal => State::equals,
+        ComparisonOperator::NotEqual => State::not_equals,
+    }
+}
+
+fn replace<S: Stack>(
+    context: &mut Context<S>,
+    node: Node,
+    operation: Operation<S>,
+    operands: &[Value],
+    operator_pos: &Pos,
+) -> Node {
+    match evaluate(context, operation, operands) {
+        Outcome::Folded(value) => match literal(value) {
+            Some(kind) => Node {
+                kind,
+                pos: node.pos,
+            },
+            None => node,
+        },
+        Outcome::Failed(message) => {
+            context.warnings.push(Warning {
+                message: format!("Expression always fails: {message}").into(),
+                pos: operator_pos.clone(),
+            });
+            node
+        }
+        Outcome::Unknown => node,
+    }
+}
+
+fn fold_box<S: Stack>(context: &mut Context<S>, node: Node) -> Box<Node> {
+    Box::new(fold_node(context, node))
+}
+
+fn fold_all<S: Stack>(context: &mut Context<S>, nodes: Vec<Node>) -> Vec<Node> {
+    nodes
+        .into_iter()
+        .map(|node| fold_node(context, node))
+        .collect()
+}
+
+fn comparison<S: Stack>(context: &mut Context<S>, node: Node) -> Node {
+    let Kind::Comparison { first, rest } = node.kind else {
+        return node;
+    };
+    let first = fold_box(context, *first);
+    let rest: Vec<_> = rest
+        .into_iter()
+        .map(|(operator, pos, operand)| (operator, pos, fold_node(context, operand)))
+        .collect();
+    let mut folded = true;
+    let mut left = constant(&first);
+    for (operator, pos, operand) in &rest {
+        let (Some(l), Some(r)) = (left.take(), constant(operand)) else {
+            folded = false;
+            break;
+        };
+        match evaluate(context, comparison_operation(*operator), &[l, r.clone()]) {
+            Outcome::Folded(Value::Boolean(true)) => left = Some(r),
+            Outcome::Folded(_) => break,
+            Outcome::Failed(message) => {
+                context.warnings.push(Warning {
+                    message: format!("Expression always fails: {message}").into(),
+                    pos: pos.clone(),
+                });
+                folded = false;
+                break;
+            }
+            Outcome::Unknown => {
+                folded = false;
+                break;
+            }
+        }
+    }
+    if folded {
+        return Node {
+            kind: Kind::Boolean(left.is_some()),
+            pos: node.pos,
+        };
+    }
+    Node {
+        kind: Kind::Comparison { first, rest },
+        pos: node.pos,
+    }
+}
+
+fn fold_node<S: Stack>(context: &mut Context<S>, node: Node) -> Node {
+    let pos = node.pos;
+    let kind = match node.kind {
+        Kind::Binary {
+            operator,
+            operator_pos,
+            left,
+            right,
+        } => {
+            let left = fold_box(context, *left);
+            let right = fold_box(context, *right);
+            let operands = constant(&left).zip(constant(&right));
+            let node = Node {
+                kind: Kind::Binary {
+                    operator,
+                    operator_pos: operator_pos.clone(),
+                    left,
+                    right,
+                },
+                pos,
+            };
+            return match operands {
+                Some((l, r)) if !is_large(operator, &l, &r) => replace(
+                    context,
+                    node,
+                    binary_operation(operator),
+                    &[l, r],
+                    &operator_pos,
+                ),
+                _ => node,
+            };
+        }
+        Kind::Negate {
+            operand,
+            operator_pos,
+        } => {
+            let operand = fold_box(context, *operand);
+            let value = constant(&operand);
+            let node = Node {
+                kind: Kind::Negate {
+                    operand,
+                    operator_pos: operator_pos.clone(),
+                },
+                pos,
+            };
+            return match value {
+                Some(value) => replace(context, node, State::negate, &[value], &operator_pos),
+                None => node,
+            };
+        }
+        kind @ Kind::Comparison { .. } => return comparison(context, Node { kind, pos }),
+        Kind::Coalesce {
+            operator_pos,
+            left,
+            right,
+        } => {
+            let left = fold_box(context, *left);
+            let right = fold_box(context, *right);
+            match constant(&left) {
+                Some(Value::Nil) => return *right,
+                Some(_) => return *left,
+                None => Kind::Coalesce {
+                    operator_pos,
+                    left,
+                    right,
+                },
+            }
+        }
+        Kind::Conditional {
+            condition,
+            question_pos,
+            then,
+            colon_pos,
+            otherwise,
+        } => {
+            let condition = fold_box(context, *condition);
+            let then = fold_box(context, *then);
+            let otherwise = fold_box(context, *otherwise);
+            match condition.kind {
+                Kind::Boolean(true) => return *then,
+                Kind::Boolean(false) => return *otherwise,
+                _ => Kind::Conditional {
+                    condition,
+                    question_pos,
+                    then,
+                    colon_pos,
+                    otherwise,
+                },
+            }
+        }
+        Kind::Sequence(nodes) => {
+            let count = nodes.len();
+            let mut nodes: Vec<_> = fold_all(context, nodes)
+                .into_iter()
+                .enumerate()
+                .filter(|(index, node)| index + 1 == count || !is_pure(node))
+                .map(|(_, node)| node)
+                .collect();
+            if nodes.len() == 1 {
+                return nodes.remove(0);
+            }
+            Kind::Sequence(nodes)
+        }
+        Kind::Assign {
+            name,
+            name_pos,
+            value,
+        } => Kind::Assign {
+            name,
+            name_pos,
+            value: fold_box(context, *value),
+        },
+        Kind::Call { name, arguments } => Kind::Call {
+            name,
+            arguments: fold_all(context, arguments),
+        },
+        Kind::Field {
+            object,
+            name,
+            access_pos,
+            safe,
+        } => Kind::Field {
+            object: fold_box(context, *object),
+            name,
+            access_pos,
+            safe,
+        },
+        Kind::Method {
+            receiver,
+            name,
+            arguments,
+            access_pos,
+            safe,
+        } => Kind::Method {
+            receiver: fold_box(context, *receiver),
+            name,
+            arguments: fold_all(context, arguments),
+            access_pos,
+            safe,
+        },
+        Kind::Unwrap {
+            operand,
+            operator_pos,
+        } => Kind::Unwrap {
+            operand: fold_box(context, *operand),
+            operator_pos,
+        },
+        Kind::Result { ok, value } => Kind::Result {
+            ok,
+            value: fold_box(context, *value),
+        },
+        Kind::Throw(value) => Kind::Throw(fold_box(context, *value)),
+        Kind::Return(value) => Kind::Return(fold_box(context, *value)),
+        Kind::Try {
+            keyword_pos,
+            body,
+            catch,
+            finally,
+        } => Kind::Try {
+            keyword_pos,
+            body: fold_box(context, *body),
+            catch: catch.map(|catch| Catch {
+                name: catch.name,
+                name_pos: catch.name_pos,
+                body: fold_box(context, *catch.body),
+            }),
+            finally: finally.map(|finally| fold_box(context, *finally)),
+        },
+        Kind::Function {
+            name,
+            name_pos,
+            parameters,
+            body,
+        } => Kind::Function {
+            name,
+            name_pos,
+            parameters,
+            body: fold_box(context, *body),
+        },
+        kind => kind,
+    };
+    Node { kind, pos }
+}
+
+pub fn fold(node: Node, warnings: &mut Vec<Warning>) -> Node {
+    let mut context = Context {
+        state: State::new(data_stack::new(static_data::new::<2>())),
+        warnings: Vec::new(),
+    };
+    let node = fold_node(&mut context, node);
+    warnings.append(&mut context.warnings);
+    node
+}
@@ -2,6 +2,12 @@ pub trait GetByte {
     fn get_byte(&self, address: usize) -> Option<u8>;
 }
 
+impl GetByte for Vec<u8> {
+    fn get_byte(&self, address: usize) -> Option<u8> {
+        self.get(address).cloned()
+    }
+}
+
 pub trait GetData<T> {
     fn get_data(&self, address: usize) -> Option<T>;
 }
@@ -30,4 +36,4 @@ macro_rules! impl_get_data {
     };
 }
 
-impl_get_data!(i64, f64);
+impl_get_data!(u8, u16, u32, i8, i16, i32, i64, f64);
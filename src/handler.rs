@@ -0,0 +1,58 @@
+use core::ops::Range;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Handler {
+    pub start: usize,
+    pub end: usize,
+    pub target: usize,
+    pub depth: usize,
+}
+
+#[derive(Clone)]
+pub struct HandlerTable(Vec<Handler>);
+
+impl HandlerTable {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn push(&mut self, handler: Handler) {
+        self.0.push(handler)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Handler> {
+        self.0.iter()
+    }
+
+    pub fn find(&self, address: usize) -> Option<&Handler> {
+        self.0
+            .iter()
+            .find(|handler| (handler.start..handler.end).contains(&address))
+    }
+}
+
+impl Default for HandlerTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn ranges(start: usize, end: usize, holes: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut holes: Vec<_> = holes
+        .iter()
+        .filter(|hole| start <= hole.start && hole.end <= end)
+        .collect();
+    holes.sort_by_key(|hole| hole.start);
+    let mut ranges = Vec::new();
+    let mut from = start;
+    for hole in holes {
+        if from < hole.start {
+            ranges.push(from..hole.start);
+        }
+        from = from.max(hole.end);
+    }
+    if from < end || ranges.is_empty() {
+        ranges.push(from..end);
+    }
+    ranges
+}
@@ -8,6 +8,10 @@ pub trait Data {
     fn get(&self, index: usize) -> Option<&Value>;
     fn get_mut(&mut self, index: usize) -> Option<&mut Value>;
     fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 struct DataStack<D> {
@@ -25,9 +29,9 @@ impl<D: Data> Stack for DataStack<D> {
     fn push(&mut self, value: Value) -> VMResult<()> {
         self.data
             .get_mut(self.top)
-            .and_then(|d| {
+            .map(|d| {
                 self.top += 1;
-                Some(*d = value)
+                *d = value
             })
             .ok_or(VMError::StackOverflow)
     }
@@ -38,11 +42,41 @@ impl<D: Data> Stack for DataStack<D> {
         } else {
             self.top -= 1;
             self.data
-                .get(self.top)
-                .cloned()
+                .get_mut(self.top)
+                .map(|d| core::mem::replace(d, Value::Void))
                 .ok_or(VMError::StackOverflow)
         }
     }
+
+    fn len(&self) -> usize {
+        self.top
+    }
+
+    fn get(&self, index: usize) -> VMResult<Value> {
+        match self.data.get(index) {
+            Some(value) if index < self.top => Ok(value.clone()),
+            _ => Err(VMError::StackUnderflow),
+        }
+    }
+
+    fn set(&mut self, index: usize, value: Value) -> VMResult<()> {
+        match self.data.get_mut(index) {
+            Some(slot) if index < self.top => {
+                *slot = value;
+                Ok(())
+            }
+            _ => Err(VMError::StackUnderflow),
+        }
+    }
+
+    fn clear(&mut self) {
+        while self.top != 0 {
+            self.top -= 1;
+            if let Some(d) = self.data.get_mut(self.top) {
+                *d = Value::Void;
+            }
+        }
+    }
 }
 
 pub fn new<D: Data>(data: D) -> impl Stack {
@@ -26,5 +26,5 @@ impl<'a> Reader for SliceReader<'a> {
 }
 
 pub fn new(slice: &[u8]) -> impl Reader + '_ {
-    SliceReader::new(slice.as_ref())
+    SliceReader::new(slice)
 }
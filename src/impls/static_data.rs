@@ -6,7 +6,7 @@ struct StaticData<const SIZE: usize>([Value; SIZE]);
 
 impl<const SIZE: usize> StaticData<SIZE> {
     fn new() -> Self {
-        Self([Value::Void; SIZE])
+        Self(core::array::from_fn(|_| Value::Void))
     }
 }
 
@@ -1,4 +1,4 @@
-use crate::push::{IntoGetByte, PushByte};
+use crate::push::{IntoGetByte, PatchByte, PushByte};
 
 use super::boxed_get::BoxedGet;
 
@@ -16,6 +16,12 @@ impl PushByte for VecPush {
     }
 }
 
+impl PatchByte for VecPush {
+    fn patch_byte(&mut self, address: usize, value: u8) {
+        self.0[address] = value
+    }
+}
+
 impl IntoGetByte for VecPush {
     type Target = BoxedGet;
 
@@ -24,6 +30,6 @@ impl IntoGetByte for VecPush {
     }
 }
 
-pub fn new() -> impl PushByte + IntoGetByte {
+pub fn new() -> impl PushByte + PatchByte + IntoGetByte {
     VecPush::new()
 }
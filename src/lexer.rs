@@ -1,4 +1,4 @@
-use crate::token::*;
+use crate::{bigint::BigInt, token::*};
 
 pub trait Reader {
     fn current(&mut self) -> Option<u8>;
@@ -55,32 +55,66 @@ fn lex_greater<R: Reader>(reader: &mut R, c0: u8) -> Token {
     }
 }
 
+fn lex_question<R: Reader>(reader: &mut R, c0: u8) -> Token {
+    match reader.current() {
+        Some(c1) if c1 == c0 || c1 == b'.' => lex_double(reader, c0, c1),
+        _ => Token::Single(c0),
+    }
+}
+
+fn lex_repeated<R: Reader>(reader: &mut R, c0: u8) -> Token {
+    if reader.current() == Some(c0) {
+        lex_double(reader, c0, c0)
+    } else {
+        Token::Single(c0)
+    }
+}
+
 fn lex_number<R: Reader>(reader: &mut R, c: u8) -> Token {
-    let mut result = (c - b'0') as i64;
-    let mut digits_after_dot = 0u32;
+    let mut text = String::new();
+    text.push(c as char);
+    let mut is_real = false;
     while let Some(c) = reader.current() {
         if c.is_ascii_digit() {
-            result = result * 10 + (c - b'0') as i64;
+            text.push(c as char);
+            reader.advance();
+        } else if c == b'.' && !is_real {
+            is_real = true;
+            text.push(c as char);
             reader.advance();
-            if digits_after_dot != 0 {
-                digits_after_dot += 1;
-            }
-        } else if c == b'.' {
-            if digits_after_dot == 0 {
-                digits_after_dot = 1;
-                reader.advance();
-            } else {
-                break;
-            }
         } else {
             break;
         }
     }
-    if digits_after_dot == 0 {
-        Token::Integer(result)
+    let token = if is_real {
+        text.parse()
+            .ok()
+            .filter(|value: &f64| value.is_finite())
+            .map(Token::Real)
+    } else if let Ok(value) = text.parse() {
+        Some(Token::Integer(value))
     } else {
-        Token::Real(result as f64 / 10u64.pow(digits_after_dot - 1) as f64)
+        BigInt::parse(&text).map(Token::BigInteger)
+    };
+    token.unwrap_or_else(|| Token::Error(format!("Invalid number literal '{text}'.").into()))
+}
+
+fn is_identifier_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
+}
+
+fn lex_identifier<R: Reader>(reader: &mut R, c: u8) -> Token {
+    let mut name = String::new();
+    name.push(c as char);
+    while let Some(c) = reader.current() {
+        if is_identifier_start(c) || c.is_ascii_digit() {
+            name.push(c as char);
+            reader.advance();
+        } else {
+            break;
+        }
     }
+    Token::Identifier(name.into_boxed_str())
 }
 
 fn lex_token<R: Reader>(reader: &mut R) -> Option<Token> {
@@ -92,6 +126,9 @@ fn lex_token<R: Reader>(reader: &mut R) -> Option<Token> {
         b'!' => lex_exclamation(reader, c),
         b'<' => lex_less(reader, c),
         b'>' => lex_greater(reader, c),
+        b'*' | b'/' | b'%' => lex_repeated(reader, c),
+        b'?' => lex_question(reader, c),
+        c if is_identifier_start(c) => lex_identifier(reader, c),
         _ => Token::Single(c),
     })
 }
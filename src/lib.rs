@@ -1,11 +1,27 @@
+pub mod assembler;
+pub mod ast;
+pub mod backend;
+pub mod bigint;
 pub mod compiler;
+pub mod convert;
+pub mod decode;
+pub mod disassembler;
+pub mod engine;
+pub mod file;
+pub mod fold;
 pub mod get;
+pub mod handler;
 pub mod impls;
 pub mod lexer;
 pub mod line;
+pub mod native;
 pub mod opcode;
+pub mod parser;
+pub mod peephole;
 pub mod push;
+pub mod register;
 pub mod state;
 pub mod token;
 pub mod value;
+pub mod verify;
 pub mod vm;
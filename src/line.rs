@@ -1,6 +1,36 @@
 use crate::lexer::Reader;
 use core::ops::Range;
 
+#[derive(Clone)]
+pub struct LineTable(Vec<(usize, Range<usize>)>);
+
+impl LineTable {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn push(&mut self, address: usize, pos: Range<usize>) {
+        self.0.push((address, pos))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(usize, Range<usize>)> {
+        self.0.iter()
+    }
+
+    pub fn find(&self, address: usize) -> Option<Range<usize>> {
+        self.0
+            .binary_search_by_key(&address, |(a, _)| *a)
+            .ok()
+            .map(|index| self.0[index].1.clone())
+    }
+}
+
+impl Default for LineTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct LineInfo {
     pub start: usize,
     pub number: usize,
@@ -28,7 +58,7 @@ pub fn create<R: Reader>(mut reader: R, start: usize) -> LineInfo {
 }
 
 pub fn print_line<R: Reader>(mut reader: R, start: usize) {
-    while let Some(_) = reader.current() {
+    while reader.current().is_some() {
         if reader.offset() < start {
             reader.advance();
         } else {
@@ -1,60 +1,86 @@
 use std::io::Write;
 
 use tpc::{
-    compiler::{self, CompileError},
-    impls::{
-        data_stack, slice_reader, static_data, token_stream,
-        vec_push::{self},
-    },
+    compiler::{CompileError, Warning},
+    disassembler,
+    engine::{self, Engine, Error, RuntimeError},
+    impls::slice_reader,
     line,
-    push::IntoGetByte,
-    state,
+    state::Stack,
+    token::Pos,
     value::Value,
-    vm,
 };
 
-fn print_error(error: CompileError, slice: &[u8]) {
-    let line_info = line::create(slice_reader::new(slice), error.pos.start);
+fn print_pos(pos: Pos, slice: &[u8]) {
+    let line_info = line::create(slice_reader::new(slice), pos.start);
     println!("In file: \"stdin\", line: {}", line_info.number);
-    line::print_line(slice_reader::new(slice.as_ref()), line_info.start);
-    line::mark_range(line_info.start, error.pos);
+    line::print_line(slice_reader::new(slice), line_info.start);
+    line::mark_range(line_info.start, pos);
+}
+
+fn print_error(error: CompileError, slice: &[u8]) {
+    print_pos(error.pos, slice);
     println!("{}", error.message);
 }
 
-fn run_slice<S: AsRef<[u8]>>(slice: S) -> Option<Value> {
-    let reader = slice_reader::new(slice.as_ref());
-    let mut stream = token_stream::new(reader);
-    let mut builder = vec_push::new();
-    match compiler::compile(&mut stream, &mut builder) {
-        Ok(_) => (),
-        Err(error) => {
-            print_error(error, slice.as_ref());
-            return None;
-        }
+fn print_runtime_error(error: RuntimeError, slice: &[u8]) {
+    if let Some(pos) = error.pos.clone() {
+        print_pos(pos, slice);
     }
-    let program = builder.into_get_byte();
-    let mut state = state::State::new(data_stack::new(static_data::new::<256>()));
-    match vm::run(&mut state, &program) {
+    println!("Runtime error: {error}");
+    for pos in error.backtrace {
+        println!("Called from:");
+        print_pos(pos, slice);
+    }
+}
+
+fn print_warning(warning: &Warning, slice: &[u8]) {
+    print_pos(warning.pos.clone(), slice);
+    println!("Warning: {}", warning.message);
+}
+
+fn run_slice<S: Stack>(engine: &mut Engine<S>, source: &str) -> Option<Value> {
+    let result = engine.compile(source).and_then(|program| {
+        for warning in program.warnings() {
+            print_warning(warning, source.as_bytes());
+        }
+        engine.run(&program)
+    });
+    match result {
         Ok(value) => Some(value),
+        Err(Error::Compile(error)) => {
+            print_error(error, source.as_bytes());
+            None
+        }
+        Err(Error::Runtime(error)) => {
+            print_runtime_error(error, source.as_bytes());
+            None
+        }
         Err(error) => {
-            if let Some(message) = state.message {
-                println!("Runtime error: {message}");
-            } else {
-                println!("Runtime error: {error}");
-            }
+            println!("{error}");
             None
         }
     }
 }
 
 fn main() {
+    let mut engine = engine::new();
     let mut line = String::new();
     loop {
         line.clear();
         print!("-> ");
         std::io::stdout().flush().unwrap();
         std::io::stdin().read_line(&mut line).unwrap();
-        if let Some(value) = run_slice(&line) {
+        if let Some(source) = line.strip_prefix(":dis ") {
+            match engine.compile(source) {
+                Ok(program) => print!(
+                    "{}",
+                    disassembler::disassemble(&program, Some(program.lines()), Some(source))
+                ),
+                Err(Error::Compile(error)) => print_error(error, source.as_bytes()),
+                Err(error) => println!("{error}"),
+            }
+        } else if let Some(value) = run_slice(&mut engine, &line) {
             println!("{value}");
         }
     }
@@ -62,5 +88,1145 @@ fn main() {
 
 #[test]
 fn base_test() {
-    assert_eq!(run_slice("2 + 2 * 2"), Some(Value::Integer(6)))
+    assert_eq!(
+        run_slice(&mut engine::new(), "2 + 2 * 2"),
+        Some(Value::Integer(6))
+    )
+}
+
+#[test]
+fn yield_test() {
+    use tpc::{
+        compiler,
+        impls::{data_stack, static_data, token_stream, vec_push},
+        push::{IntoGetByte, PushByte},
+        state, vm,
+    };
+
+    struct Count(usize);
+    impl PushByte for Count {
+        fn push_byte(&mut self, _: u8) {
+            self.0 += 1;
+        }
+    }
+    let mut stream = token_stream::new(slice_reader::new(b"1; yield; 2 + 3"));
+    let mut count = Count(0);
+    assert!(compiler::compile(&mut stream, &mut count).is_ok());
+    assert_eq!(count.0, 5);
+
+    let mut stream = token_stream::new(slice_reader::new(b"1; yield; 2 + 3"));
+    let mut builder = vec_push::new();
+    assert!(compiler::compile(&mut stream, &mut builder).is_ok());
+    let program = builder.into_get_byte();
+    let mut state = state::State::new(data_stack::new(static_data::new::<256>()));
+    assert!(matches!(
+        vm::run_until(&mut state, &program),
+        Ok(vm::Status::Yielded)
+    ));
+    assert!(matches!(
+        vm::run_for(&mut state, &program, 1),
+        Ok(vm::Status::Paused)
+    ));
+    assert!(matches!(
+        vm::run_until(&mut state, &program),
+        Ok(vm::Status::Finished(Value::Integer(5)))
+    ));
+
+    let mut engine = engine::new();
+    let program = engine
+        .compile("x = 1; x = x + yield + 1; yield; x * 10")
+        .ok()
+        .expect("yielding script should compile");
+    engine.start(&program);
+    assert!(matches!(
+        engine.resume(&program, usize::MAX),
+        Ok(vm::Status::Yielded)
+    ));
+    assert!(matches!(
+        engine.resume_with(&program, Value::Integer(4), 1),
+        Ok(vm::Status::Paused)
+    ));
+    assert!(matches!(
+        engine.resume(&program, usize::MAX),
+        Ok(vm::Status::Yielded)
+    ));
+    assert_eq!(engine.global("x"), Some(Value::Integer(6)));
+    assert!(matches!(
+        engine.resume(&program, usize::MAX),
+        Ok(vm::Status::Finished(Value::Integer(60)))
+    ));
+    let program = engine
+        .compile("try { throw 1 } catch e { e }")
+        .ok()
+        .expect("throwing script should compile");
+    engine.start(&program);
+    assert!(matches!(engine.resume(&program, 6), Ok(vm::Status::Paused)));
+    assert!(matches!(
+        engine.resume(&program, 1),
+        Ok(vm::Status::Finished(Value::Integer(1)))
+    ));
+    assert!(matches!(
+        engine.eval("1; yield; 2 + 3"),
+        Ok(Value::Integer(5))
+    ));
+}
+
+#[test]
+fn native_test() {
+    let mut engine = engine::new();
+    engine.register("max", 2, |_, args| match (&args[0], &args[1]) {
+        (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(*l.max(r))),
+        _ => Ok(Value::Void),
+    });
+    assert_eq!(engine.eval("max(2, 3) * 2").ok(), Some(Value::Integer(6)));
+    match engine.eval("mx(2, 3)") {
+        Err(Error::Compile(error)) => assert_eq!(error.pos, 0..2),
+        _ => panic!("expected compile error"),
+    }
+}
+
+#[test]
+fn typed_native_test() {
+    let mut engine = engine::new();
+    engine.register_fn("above", |value: i64, limit: f64| value as f64 > limit);
+    assert_eq!(
+        engine.eval("above(3, 2.5)").ok(),
+        Some(Value::Boolean(true))
+    );
+    match engine.eval("above(3.5, 2)") {
+        Err(Error::Runtime(error)) => assert_eq!(
+            error.message.as_deref(),
+            Some("Native function 'above' argument 1: expected integer, found real '3.5'.")
+        ),
+        _ => panic!("expected runtime error"),
+    }
+}
+
+#[test]
+fn globals_test() {
+    let mut engine = engine::new();
+    assert_eq!(
+        engine.eval("x = 2; y = x * 3").ok(),
+        Some(Value::Integer(6))
+    );
+    assert_eq!(engine.eval("x + y").ok(), Some(Value::Integer(8)));
+    assert_eq!(engine.global("y"), Some(Value::Integer(6)));
+    assert!(engine.eval("z + 1").is_err());
+}
+
+#[test]
+fn runtime_pos_test() {
+    match engine::new().eval("1 + 2 / (3 - 3)") {
+        Err(Error::Runtime(error)) => assert_eq!(error.pos, Some(6..7)),
+        _ => panic!("expected runtime error"),
+    }
+    let source = "fn g(x) { x / 0 }; fn f(x) { g(x) + 1 }; f(2)";
+    match engine::new().eval(source) {
+        Err(Error::Runtime(error)) => {
+            assert_eq!(error.pos, Some(12..13));
+            assert_eq!(error.backtrace, [29..33, 41..45]);
+        }
+        _ => panic!("expected runtime error"),
+    }
+    match tpc::register::engine::new().eval(source) {
+        Err(Error::Runtime(error)) => assert_eq!(error.backtrace, [29..33, 41..45]),
+        _ => panic!("expected runtime error"),
+    }
+}
+
+#[test]
+fn arithmetic_test() {
+    use tpc::state::Arithmetic;
+
+    let mut engine = engine::new();
+    assert!(engine
+        .eval("min = 0 - 9223372036854775807 - 1; max = 9223372036854775807")
+        .is_ok());
+    assert_eq!(engine.eval("max + 1").ok(), engine.global("min"));
+    engine.set_arithmetic(Arithmetic::Saturating);
+    assert_eq!(engine.eval("min / (0 - 1)").ok(), engine.global("max"));
+    engine.set_arithmetic(Arithmetic::Checked);
+    match engine.eval("min % (0 - 1)") {
+        Err(Error::Runtime(error)) => assert_eq!(
+            error.message.as_deref(),
+            Some("Integer overflow in '%' for -9223372036854775808 and -1.")
+        ),
+        _ => panic!("expected runtime error"),
+    }
+    match engine.eval("1 / 0") {
+        Err(Error::Runtime(error)) => assert_eq!(
+            error.message.as_deref(),
+            Some("Unable to use '/' for 1 and 0, dividing by zero.")
+        ),
+        _ => panic!("expected runtime error"),
+    }
+}
+
+#[test]
+fn big_integer_test() {
+    use tpc::state::Arithmetic;
+
+    let mut engine = engine::new();
+    engine.set_arithmetic(Arithmetic::Promoting);
+    let result = engine.eval("9223372036854775807 * 4 + 1").ok();
+    assert_eq!(
+        result.map(|v| v.to_string()).as_deref(),
+        Some("36893488147419103229")
+    );
+    assert_eq!(
+        engine
+            .eval("100000000000000000000 - 99999999999999999999")
+            .ok(),
+        Some(Value::Integer(1))
+    );
+    assert_eq!(engine.eval("(1 << 70) >> 68").ok(), Some(Value::Integer(4)));
+    assert_eq!(
+        engine
+            .eval("18446744073709551616 > 9223372036854775807")
+            .ok(),
+        Some(Value::Boolean(true))
+    );
+}
+
+#[test]
+fn power_test() {
+    let mut engine = engine::new();
+    assert_eq!(engine.eval("2 ** 3 ** 2").ok(), Some(Value::Integer(512)));
+    assert_eq!(engine.eval("-2 ** 2").ok(), Some(Value::Integer(-4)));
+    assert_eq!(engine.eval("2 * 3 ** 2").ok(), Some(Value::Integer(18)));
+    assert_eq!(engine.eval("2 ** -1").ok(), Some(Value::Real(0.5)));
+    assert_eq!(engine.eval("4 ** 0.5").ok(), Some(Value::Real(2.0)));
+    assert!(engine.eval("0 ** -1").is_err());
+}
+
+#[test]
+fn floor_division_test() {
+    let mut engine = engine::new();
+    assert_eq!(engine.eval("-7 % 3").ok(), Some(Value::Integer(-1)));
+    assert_eq!(engine.eval("-7 %% 3").ok(), Some(Value::Integer(2)));
+    assert_eq!(engine.eval("7 %% -3").ok(), Some(Value::Integer(1)));
+    assert_eq!(engine.eval("-7 // 2").ok(), Some(Value::Integer(-4)));
+    assert_eq!(engine.eval("7 // -2").ok(), Some(Value::Integer(-4)));
+    assert_eq!(engine.eval("-7.5 // 2").ok(), Some(Value::Real(-4.0)));
+    assert!(engine.eval("1 // 0").is_err());
+}
+
+#[test]
+fn equality_test() {
+    let mut engine = engine::new();
+    assert_eq!(engine.eval("true == true").ok(), Some(Value::Boolean(true)));
+    assert_eq!(engine.eval("() == ()").ok(), Some(Value::Boolean(true)));
+    assert_eq!(engine.eval("1 == true").ok(), Some(Value::Boolean(false)));
+    assert_eq!(engine.eval("1 != ()").ok(), Some(Value::Boolean(true)));
+    assert_eq!(engine.eval("2 == 2.0").ok(), Some(Value::Boolean(true)));
+    assert_eq!(engine.eval("false < true").ok(), Some(Value::Boolean(true)));
+    assert!(engine.eval("1 < true").is_err());
+}
+
+#[test]
+fn ordering_test() {
+    use core::cmp::Ordering;
+
+    let nan = Value::Real(f64::NAN);
+    assert!(!nan.equals(&nan));
+    assert_eq!(nan.total_cmp(&nan), Ordering::Equal);
+    assert_eq!(nan.total_cmp(&Value::Integer(i64::MAX)), Ordering::Greater);
+    assert_eq!(
+        Value::Integer(1).total_cmp(&Value::Real(1.0)),
+        Ordering::Equal
+    );
+    assert_eq!(
+        Value::Integer(i64::MAX).total_cmp(&Value::Real(9223372036854775807.0)),
+        Ordering::Less
+    );
+    assert_eq!(
+        Value::Void.total_cmp(&Value::Boolean(false)),
+        Ordering::Less
+    );
+}
+
+#[test]
+fn chained_comparison_test() {
+    let mut engine = engine::new();
+    engine.register_fn("count", |value: i64| value);
+    assert_eq!(engine.eval("1 < 2 < 3").ok(), Some(Value::Boolean(true)));
+    assert_eq!(engine.eval("1 < 3 < 2").ok(), Some(Value::Boolean(false)));
+    assert_eq!(
+        engine.eval("0 <= 5 < 10 == true").ok(),
+        Some(Value::Boolean(false))
+    );
+    assert_eq!(
+        engine.eval("3 < 2 < 1 / 0").ok(),
+        Some(Value::Boolean(false))
+    );
+    assert_eq!(
+        engine.eval("i = 4; 0 <= i < 5").ok(),
+        Some(Value::Boolean(true))
+    );
+    assert_eq!(engine.eval("1 + (1 < 2 < 3 == true)").ok(), None);
+}
+
+#[test]
+fn conditional_test() {
+    let mut engine = engine::new();
+    engine.register_fn("find", |value: i64| (value > 0).then_some(value));
+    assert_eq!(
+        engine.eval("true ? 1 : 1 / 0").ok(),
+        Some(Value::Integer(1))
+    );
+    assert_eq!(
+        engine.eval("1 > 2 ? 1 / 0 : 2").ok(),
+        Some(Value::Integer(2))
+    );
+    assert_eq!(
+        engine.eval("false ? 1 : true ? 2 : 3").ok(),
+        Some(Value::Integer(2))
+    );
+    assert_eq!(engine.eval("1 ? 2 : 3").ok(), None);
+    assert_eq!(
+        engine.eval("find(0) ?? find(-1) ?? 5").ok(),
+        Some(Value::Integer(5))
+    );
+    assert_eq!(
+        engine.eval("find(4) ?? 1 / 0").ok(),
+        Some(Value::Integer(4))
+    );
+    assert_eq!(
+        engine.eval("(find(4) ?? 0) == 4 ? 1 : 2").ok(),
+        Some(Value::Integer(1))
+    );
+}
+
+#[test]
+fn nil_test() {
+    use std::collections::HashMap;
+
+    let mut engine = engine::new();
+    engine.register_fn("list", || vec![1, 2]);
+    engine.register_fn("user", || {
+        HashMap::from([("name", Value::String("Ann".into())), ("boss", Value::Nil)])
+    });
+    engine.register_fn("find", |value: i64| (value > 0).then_some(value));
+    assert_eq!(engine.eval("nil").ok(), Some(Value::Nil));
+    assert_eq!(
+        engine.eval("find(0) == nil").ok(),
+        Some(Value::Boolean(true))
+    );
+    assert_eq!(
+        engine.eval("user().name").ok(),
+        Some(Value::String("Ann".into()))
+    );
+    assert_eq!(engine.eval("user().age").ok(), Some(Value::Nil));
+    assert_eq!(engine.eval("user().boss?.name.size").ok(), Some(Value::Nil));
+    assert_eq!(
+        engine.eval("user()?.age ?? 30").ok(),
+        Some(Value::Integer(30))
+    );
+    assert!(engine.eval("user().boss.name").is_err());
+    assert_eq!(
+        engine.eval("l = list(); l.pop(); pop(l); l.pop()").ok(),
+        Some(Value::Nil)
+    );
+    match engine.eval("1 + nil") {
+        Err(error) => assert!(error.to_string().contains("nil")),
+        Ok(_) => panic!("nil arithmetic should fail"),
+    }
+}
+
+#[test]
+fn exception_test() {
+    let mut engine = engine::new();
+    assert_eq!(
+        engine.eval("try { throw 42 } catch e { e + 1 }").ok(),
+        Some(Value::Integer(43))
+    );
+    assert_eq!(
+        engine
+            .eval("1 + try { 2 + throw 5 } catch e { e * 10 }")
+            .ok(),
+        Some(Value::Integer(51))
+    );
+    assert_eq!(
+        engine.eval("try { 1 / 0 } catch e { e }").ok(),
+        Some(Value::String(
+            "Unable to use '/' for 1 and 0, dividing by zero.".into()
+        ))
+    );
+    assert_eq!(
+        engine
+            .eval("try { try { throw 1 } finally { n = 2 } } catch e { e + n }")
+            .ok(),
+        Some(Value::Integer(3))
+    );
+    assert_eq!(
+        engine.eval("try { 4 } finally { n = 5 }; n").ok(),
+        Some(Value::Integer(5))
+    );
+    match engine.eval("try { throw 1 } catch e { throw e + 1 } finally { n = 6 }") {
+        Err(error) => assert_eq!(error.to_string(), "Runtime error: Uncaught exception '2'."),
+        Ok(_) => panic!("exception should escape"),
+    }
+    assert_eq!(engine.global("n"), Some(Value::Integer(6)));
+
+    let mut machine = tpc::register::engine::new();
+    for result in [
+        engine.eval("try { fn f() { throw 1 }; 0 } catch e { 100 }; f() + 5"),
+        machine.eval("try { fn f() { throw 1 }; 0 } catch e { 100 }; f() + 5"),
+    ] {
+        match result {
+            Err(error) => assert_eq!(error.to_string(), "Runtime error: Uncaught exception '1'."),
+            Ok(_) => panic!("exception should escape"),
+        }
+    }
+    let source = "try { fn f() { throw 1 }; 0 } catch e { 100 }; try { f() } catch e { e + 10 }";
+    assert_eq!(engine.eval(source).ok(), Some(Value::Integer(11)));
+    assert_eq!(machine.eval(source).ok(), Some(Value::Integer(11)));
+    let source = "try { fn g() { throw 2 }; g() } catch e { e * 10 }";
+    assert_eq!(engine.eval(source).ok(), Some(Value::Integer(20)));
+    assert_eq!(machine.eval(source).ok(), Some(Value::Integer(20)));
+
+    for source in [
+        "n = 0; fn f() { try { return 1 } finally { n = 2 } }; f(); n",
+        "fn h(r) { try { 0 } catch e { r? } finally { 1 } }; h(Ok(1))",
+    ] {
+        for result in [engine.eval(source), machine.eval(source)] {
+            match result {
+                Err(Error::Compile(error)) => assert!(
+                    error
+                        .message
+                        .to_string()
+                        .ends_with("inside a 'try' with a 'finally' block."),
+                    "{source}"
+                ),
+                _ => panic!("expected compile error: {source}"),
+            }
+        }
+    }
+    for source in [
+        "fn g(r) { try { r? } catch e { 0 } }; g(Ok(1))",
+        "try { fn k() { return 1 }; k() } finally { 0 }",
+    ] {
+        assert_eq!(
+            engine.eval(source).ok(),
+            Some(Value::Integer(1)),
+            "{source}"
+        );
+        assert_eq!(
+            machine.eval(source).ok(),
+            Some(Value::Integer(1)),
+            "{source}"
+        );
+    }
+}
+
+#[test]
+fn function_test() {
+    use tpc::register;
+
+    let mut engine = engine::new();
+    assert_eq!(
+        engine
+            .eval("fn fact(n) { n < 2 ? 1 : n * fact(n - 1) }; fact(10)")
+            .ok(),
+        Some(Value::Integer(3628800))
+    );
+    assert_eq!(
+        engine
+            .eval("x = 1; fn f(a) { x = a * 2; return x + 1; 0 }; f(5) + x")
+            .ok(),
+        Some(Value::Integer(12))
+    );
+    assert_eq!(
+        engine
+            .eval("fn f() { throw 3 }; try { 1 + f() } catch e { e }")
+            .ok(),
+        Some(Value::Integer(3))
+    );
+
+    let locals = |count: usize| {
+        let body: String = (0..count).map(|i| format!("a{i} = {i}; ")).collect();
+        format!("fn f() {{ {body}a{} }}; f()", count - 1)
+    };
+    assert_eq!(engine.eval(&locals(255)).ok(), Some(Value::Integer(254)));
+    assert_eq!(
+        register::engine::new().eval(&locals(255)).ok(),
+        Some(Value::Integer(254))
+    );
+    match engine.eval(&locals(256)) {
+        Err(error) => assert_eq!(
+            error.to_string(),
+            "Compile error: Unable to define local variable 'a255'."
+        ),
+        Ok(_) => panic!("expected compile error"),
+    }
+    assert!(register::engine::new().eval(&locals(256)).is_err());
+
+    let mut machine = register::engine::new();
+    for source in ["fn f() { f() }; f()", "fn f(n) { f(n + 1) }; f(0)"] {
+        for result in [engine.eval(source), machine.eval(source)] {
+            assert!(matches!(
+                result,
+                Err(Error::Runtime(RuntimeError {
+                    error: tpc::state::VMError::StackOverflow,
+                    ..
+                }))
+            ));
+        }
+    }
+    assert_eq!(
+        engine
+            .eval("fn depth(n) { n < 200 ? depth(n + 1) : n }; depth(0)")
+            .ok(),
+        Some(Value::Integer(200))
+    );
+}
+
+#[test]
+fn result_test() {
+    let mut engine = engine::new();
+    engine.register_fn("divide", |l: i64, r: i64| {
+        l.checked_div(r)
+            .ok_or(format!("unable to divide {l} by {r}"))
+    });
+    let source = "fn f(a, b, c) { Ok(divide(a, b)? + divide(a, c)?) }; ";
+    assert_eq!(
+        engine.eval(&format!("{source}f(12, 3, 4)")).ok(),
+        Some(Value::Ok(Value::Integer(7).into()))
+    );
+    assert_eq!(
+        engine.eval(&format!("{source}f(12, 3, 0)")).ok(),
+        Some(Value::Err(
+            Value::String("unable to divide 12 by 0".into()).into()
+        ))
+    );
+    assert_eq!(
+        engine.eval("fn f(r) { r? ? 1 : 2 }; f(Ok(false))").ok(),
+        Some(Value::Integer(2))
+    );
+    assert_eq!(
+        engine.eval("fn f(r) { r? - 1 }; f(Ok(3))").ok(),
+        Some(Value::Integer(2))
+    );
+    assert_eq!(
+        engine
+            .eval("fn f(c, r) { c ? r? - 1 : (r?) }; f(true, Ok(3)) + f(false, Ok(5))")
+            .ok(),
+        Some(Value::Integer(7))
+    );
+    assert_eq!(
+        engine
+            .eval("fn f(c, r, y) { c ? (y) : r? * (y) }; f(false, Ok(3), 4)")
+            .ok(),
+        Some(Value::Integer(12))
+    );
+    assert_eq!(
+        engine
+            .eval("fn f(c, r) { c ? -1 : r?-1 }; f(true, Ok(0)) + f(false, Ok(5))")
+            .ok(),
+        Some(Value::Integer(3))
+    );
+    match engine.eval("fn f(r, y) { r? (y) }") {
+        Err(error) => assert_eq!(
+            error.to_string(),
+            "Compile error: Expected ':', found character '}'."
+        ),
+        Ok(_) => panic!("'(y)' should not follow an unwrapped value"),
+    }
+    match engine.eval("Ok(1)?") {
+        Err(error) => assert_eq!(
+            error.to_string(),
+            "Compile error: Unable to use '?' outside of a function body."
+        ),
+        Ok(_) => panic!("'?' should not compile at top level"),
+    }
+}
+
+#[test]
+fn bytecode_file_test() {
+    use tpc::file::LoadError;
+
+    let mut engine = engine::new();
+    engine.register_fn("twice", |value: i64| value * 2);
+    let program = engine
+        .compile("fn f(a) { try { a / 0 } catch e { twice(a) } }; x = f(4)")
+        .ok()
+        .unwrap();
+    let bytes = engine.save(&program, true).ok().unwrap();
+    assert_eq!(&bytes[..4], b"ARIA");
+    assert!(engine::new().save(&program, true).is_err());
+
+    let mut loaded = engine::new();
+    loaded.register_fn("twice", |value: i64| value * 2);
+    let program = loaded.load(&bytes).ok().unwrap();
+    assert_eq!(loaded.run(&program).ok(), Some(Value::Integer(8)));
+    assert_eq!(loaded.global("x"), Some(Value::Integer(8)));
+
+    let mut host = engine::new();
+    host.set_global("config", Value::Integer(1));
+    host.register_fn("unused", |value: i64| value);
+    host.register_fn("twice", |value: i64| value * 2);
+    let program = host.load(&bytes).ok().unwrap();
+    assert_eq!(host.run(&program).ok(), Some(Value::Integer(8)));
+    assert_eq!(host.global("x"), Some(Value::Integer(8)));
+    assert_eq!(host.global("config"), Some(Value::Integer(1)));
+
+    let error = |bytes: &[u8]| engine::new().load(bytes).err().map(|e| e.to_string());
+    let mut corrupted = bytes.clone();
+    corrupted[20] ^= 1;
+    let mut version = bytes.clone();
+    version[5] = 9;
+    assert_eq!(
+        error(&bytes[..bytes.len() - 3]),
+        Some(LoadError::Truncated.to_string())
+    );
+    assert_eq!(error(&corrupted), Some(LoadError::Checksum.to_string()));
+    assert_eq!(error(&version), Some(LoadError::Version(9).to_string()));
+    assert_eq!(
+        error(&bytes),
+        Some(LoadError::Native("twice".into()).to_string())
+    );
+}
+
+#[test]
+fn verify_test() {
+    use tpc::{
+        decode,
+        handler::{Handler, HandlerTable},
+        impls::vec_push,
+        opcode::*,
+        push::{IntoGetByte, PushByte},
+        verify::*,
+    };
+
+    let mut engine = engine::new();
+    let sources = [
+        "1 < 2 < 3 ? -1 : 2 ** 3",
+        "x = nil; x?.y ?? yield",
+        "fn f(a, b) { c = a; try { throw c } catch e { Ok(e + b) } finally { 0 } }; f(1, 2)",
+        "fn g(r) { r? }; g(Ok(123456789012345678901234567890))",
+    ];
+    for source in sources {
+        let program = engine.compile(source).ok().unwrap();
+        let bytes = engine.save(&program, false).ok().unwrap();
+        assert!(engine::new().load(&bytes).is_ok(), "{source}");
+    }
+
+    let handlers = HandlerTable::new();
+    let check = |code: &[u8]| {
+        let mut builder = vec_push::new();
+        for &byte in code {
+            builder.push_byte(byte);
+        }
+        verify(&builder.into_get_byte(), &handlers)
+    };
+    let jump = |target: u8| [LDT, JFP, 0, 0, 0, target, LDV, END];
+    assert_eq!(check(&jump(7)), Ok(()));
+    assert_eq!(
+        check(&jump(4)),
+        Err(VerifyError::InvalidTarget {
+            address: 1,
+            target: 4
+        })
+    );
+    assert_eq!(
+        check(&[LDI, 0, 0]),
+        Err(VerifyError::MissingOperand { address: 0 })
+    );
+    assert_eq!(
+        check(&[ADD, END]),
+        Err(VerifyError::StackUnderflow { address: 0 })
+    );
+    assert_eq!(
+        check(&[LDT, JFP, 0, 0, 0, 8, LDV, LDV, END]),
+        Err(VerifyError::InconsistentStack {
+            address: 8,
+            expected: 2,
+            found: 1
+        })
+    );
+    assert_eq!(check(&[LDT]), Err(VerifyError::FallsOffEnd { address: 1 }));
+    assert_eq!(
+        Error::Verify(VerifyError::FallsOffEnd { address: 1 }).to_string(),
+        "Invalid bytecode: Execution runs past the end of code after 1."
+    );
+
+    let code = [LDI8, 5, POP, LDV, END];
+    let mut builder = vec_push::new();
+    for byte in code {
+        builder.push_byte(byte);
+    }
+    let program = builder.into_get_byte();
+    let guarded = |start, end| {
+        let mut handlers = HandlerTable::new();
+        handlers.push(Handler {
+            start,
+            end,
+            target: 4,
+            depth: 0,
+        });
+        (
+            verify(&program, &handlers),
+            decode::decode(&program, &handlers).err(),
+        )
+    };
+    assert_eq!(guarded(0, 2), (Ok(()), None));
+    assert_eq!(guarded(0, 5), (Ok(()), None));
+    for (start, end) in [(0, 1), (0, 6), (2, 0)] {
+        let error = || VerifyError::InvalidHandler { start, end };
+        assert_eq!(guarded(start, end), (Err(error()), Some(error())));
+    }
+}
+
+#[test]
+fn disassembler_test() {
+    let mut engine = engine::new();
+    let source = "x = 2;\nx > 1 ? x.y : -1.5";
+    let program = engine.compile(source).ok().unwrap();
+    let expected = "    LDI8 2                  ; 0000 line 1: x = 2;
+    STG 0                   ; 0002
+    POP                     ; 0005
+    LDG 0                   ; 0006 line 2: x > 1 ? x.y : -1.5
+    LD1                     ; 0009
+    CJP GR L001C            ; 000A
+    LDG 0                   ; 0010
+    FLD \"y\"                 ; 0013
+    JMP L0020               ; 0017
+L001C:
+    POP                     ; 001C
+    LDC 0                   ; 001D
+L0020:
+    END                     ; 0020
+";
+    assert_eq!(
+        disassembler::disassemble(&program, Some(program.lines()), Some(source)),
+        expected
+    );
+}
+
+#[test]
+fn assembler_test() {
+    use tpc::{assembler, impls::data_stack, impls::static_data, opcode::*, state::State, vm};
+
+    let source = "
+        .native double
+        LDI 20
+        CLN double 1    ; calls the first pooled native
+        CAL add_one 1
+        END
+    add_one:
+        LCL 0
+        LDL 0
+        LDI 1
+        ADD
+        RET
+    ";
+    let mut code = Vec::new();
+    let pool = assembler::assemble(source, &mut code).ok().unwrap();
+    assert_eq!(pool.natives, vec!["double".into()]);
+    assert_eq!(&code[..2], &[LDI, 0]);
+
+    let mut state = State::new(data_stack::new(static_data::new::<16>()));
+    state.natives.register_fn("double", |value: i64| value * 2);
+    assert_eq!(vm::run(&mut state, &code).ok(), Some(Value::Integer(41)));
+
+    let error = assembler::assemble("JMP nowhere", &mut Vec::new()).err();
+    assert_eq!(
+        error.map(|e| e.to_string()),
+        Some("Line 1: Unknown label 'nowhere'.".to_string())
+    );
+}
+
+#[test]
+fn assembler_round_trip_test() {
+    use tpc::{assembler, opcode::*};
+
+    let mut seed = 0x2545F4914F6CDD1Du64;
+    let mut random = move |limit: u64| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed % limit
+    };
+    for _ in 0..200 {
+        let mut instructions = Vec::new();
+        for _ in 0..random(40) + 1 {
+            let opcode = loop {
+                let opcode = random(256) as u8;
+                if info(opcode).is_some() {
+                    break opcode;
+                }
+            };
+            let mut operands = Vec::new();
+            for operand in info(opcode).unwrap().operands {
+                let bytes = match operand {
+                    Operand::Byte => vec![random(256) as u8],
+                    Operand::Opcode => vec![random(0x31) as u8 + 1],
+                    Operand::Index | Operand::Int16 => {
+                        (random(65536) as u16).to_be_bytes().to_vec()
+                    }
+                    Operand::Int8 => vec![random(256) as u8],
+                    Operand::Int32 => (random(1 << 32) as u32).to_be_bytes().to_vec(),
+                    Operand::Address => Vec::new(),
+                    Operand::Integer => (random(u64::MAX) as i64).to_be_bytes().to_vec(),
+                    Operand::Real => {
+                        let value = random(u64::MAX) as i64 as f64 / (random(1000) + 1) as f64;
+                        value.to_be_bytes().to_vec()
+                    }
+                    Operand::Limbs => {
+                        let count = random(4) as u16;
+                        let mut bytes = count.to_be_bytes().to_vec();
+                        for _ in 0..count {
+                            bytes.extend((random(1 << 32) as u32).to_be_bytes());
+                        }
+                        bytes
+                    }
+                    Operand::Name => {
+                        let text: String = (0..random(6))
+                            .map(|_| ['a', 'Z', '_', '"', '\\', '\n', 'é', '7'][random(8) as usize])
+                            .collect();
+                        let mut bytes = (text.len() as u16).to_be_bytes().to_vec();
+                        bytes.extend(text.bytes());
+                        bytes
+                    }
+                };
+                operands.push(bytes);
+            }
+            instructions.push((opcode, operands));
+        }
+
+        let mut addresses = Vec::new();
+        let mut address = 0;
+        for (_, operands) in &instructions {
+            addresses.push(address as u32);
+            address += 1;
+            for operand in operands {
+                address += if operand.is_empty() { 4 } else { operand.len() };
+            }
+        }
+        let mut code = Vec::new();
+        for (opcode, operands) in &instructions {
+            code.push(*opcode);
+            for operand in operands {
+                if operand.is_empty() {
+                    let target = addresses[random(addresses.len() as u64) as usize];
+                    code.extend(target.to_be_bytes());
+                } else {
+                    code.extend(operand);
+                }
+            }
+        }
+
+        let text = disassembler::disassemble(&code, None, None);
+        let mut assembled = Vec::new();
+        assert!(assembler::assemble(&text, &mut assembled).is_ok(), "{text}");
+        assert_eq!(assembled, code, "{text}");
+    }
+}
+
+#[test]
+fn parser_test() {
+    use tpc::{
+        ast::{BinaryOperator, Kind},
+        impls::token_stream,
+        parser,
+    };
+
+    let mut stream = token_stream::new(slice_reader::new(b"x = 1 + 2 * y; a?.b"));
+    let node = parser::parse(&mut stream).ok().flatten().unwrap();
+    assert_eq!(node.pos, 0..19);
+    let Kind::Sequence(nodes) = node.kind else {
+        panic!("expected sequence");
+    };
+    let Kind::Assign { name, value, .. } = &nodes[0].kind else {
+        panic!("expected assignment");
+    };
+    assert_eq!(name.as_ref(), "x");
+    assert_eq!(value.pos, 4..13);
+    let Kind::Binary {
+        operator, right, ..
+    } = &value.kind
+    else {
+        panic!("expected binary");
+    };
+    assert_eq!(*operator, BinaryOperator::Add);
+    assert_eq!(right.pos, 8..13);
+    assert!(matches!(nodes[1].kind, Kind::Field { safe: true, .. }));
+    assert_eq!(nodes[1].pos, 15..19);
+
+    let mut stream = token_stream::new(slice_reader::new(b"1 +"));
+    assert!(parser::parse(&mut stream).is_err());
+
+    let literal = format!("1{}.5", "0".repeat(400));
+    let source = format!("x = 2 * {literal}");
+    let mut stream = token_stream::new(slice_reader::new(source.as_bytes()));
+    match parser::parse(&mut stream) {
+        Err(error) => {
+            assert_eq!(
+                error.message.to_string(),
+                format!("Invalid number literal '{literal}'.")
+            );
+            assert_eq!(error.pos, 8..source.len());
+        }
+        Ok(_) => panic!("expected lexer error"),
+    }
+}
+
+#[test]
+fn constant_folding_test() {
+    let mut engine = engine::new();
+    let program = engine.compile("2 + 2 * 2 < 7 ? 1 << 3 : 0").ok().unwrap();
+    assert_eq!(
+        disassembler::disassemble(&program, None, None),
+        "    LDI8 8                  ; 0000\n    END                     ; 0002\n"
+    );
+    assert!(program.warnings().is_empty());
+
+    let program = engine.compile("9223372036854775807 + 1").ok().unwrap();
+    assert!(disassembler::disassemble(&program, None, None).contains("ADI 1"));
+
+    let program = engine
+        .compile("123456789012345678901234567890 ** 2")
+        .ok()
+        .unwrap();
+    assert!(disassembler::disassemble(&program, None, None).starts_with("    LDB"));
+    for source in [
+        "2 ** 1000000",
+        "3 ** 300000",
+        "123456789012345678901234567890 ** 100000",
+        "123456789012345678901234567890 << 1000000",
+    ] {
+        let program = engine.compile(source).ok().unwrap();
+        let code = disassembler::disassemble(&program, None, None);
+        assert!(code.contains("POW") || code.contains("SHL"), "{source}");
+    }
+
+    let program = engine.compile("x = 1 / 0; 1 << -1").ok().unwrap();
+    let positions: Vec<_> = program.warnings().iter().map(|w| w.pos.clone()).collect();
+    assert_eq!(positions, [6..7, 13..15]);
+    assert!(matches!(
+        engine.run(&program),
+        Err(Error::Runtime(RuntimeError {
+            error: tpc::state::VMError::DividingByZero,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn peephole_test() {
+    let mut engine = engine::new();
+    let source = "fn count(i, n) { i = i + 1; i < n ? count(i, n) : i * 2 }; count(0, 10)";
+    let program = engine.compile(source).ok().unwrap();
+    let text = disassembler::disassemble(&program, None, None);
+    assert!(text.contains("INL 0 1"));
+    assert!(text.contains("CJP LS"));
+    assert!(text.contains("MLI 2"));
+    assert!(matches!(engine.run(&program), Ok(Value::Integer(20))));
+
+    let program = engine
+        .compile("fn f(x) { x < 0 ? throw x : x }; try { f(-1) } catch e { e - 1 }")
+        .ok()
+        .unwrap();
+    assert!(matches!(engine.run(&program), Ok(Value::Integer(-2))));
+}
+
+#[test]
+fn compact_encoding_test() {
+    let mut engine = engine::new();
+    let source =
+        "x = 0; x = 1; x = -100; x = 1000; x = -100000; x = 10000000000; x = 2.5; x = 2.5; x = 0.5";
+    let program = engine.compile(source).ok().unwrap();
+    let text = disassembler::disassemble(&program, None, None);
+    for form in [
+        "LD0 ",
+        "LD1 ",
+        "LDI8 -100",
+        "LDI16 1000",
+        "LDI32 -100000",
+        "LDI 10000000000",
+        "LDC 0",
+        "LDC 1",
+    ] {
+        assert!(text.contains(form), "missing {form}");
+    }
+    assert_eq!(text.matches("LDC 0").count(), 2);
+    assert!(matches!(engine.run(&program), Ok(Value::Real(0.5))));
+
+    let bytes = engine.save(&program, false).ok().unwrap();
+    let program = engine.load(&bytes).ok().unwrap();
+    assert!(matches!(engine.run(&program), Ok(Value::Real(0.5))));
+}
+
+#[test]
+fn register_backend_test() {
+    use tpc::{backend::Backend, register};
+
+    fn outcome<B: Backend>(backend: &mut B, source: &str) -> Result<Value, String> {
+        backend.eval(source).map_err(|error| match error {
+            Error::Compile(error) => format!("{} at {:?}", error.message, error.pos),
+            Error::Runtime(error) => format!("{error} at {:?}", error.pos),
+            error => error.to_string(),
+        })
+    }
+
+    let scripts = [
+        "1 + 2 * 3 - 4 / 2",
+        "x = 5; y = x * x; x = y - x; x",
+        "fn fib(n) { n < 2 ? n : fib(n - 1) + fib(n - 2) }; fib(15)",
+        "fn f(a, b) { c = a; a = b; b = c; a - b }; f(1, 10)",
+        "fn f(x) { x + (x = 10) }; f(1)",
+        "1 < 2 < 3 ? 3 > 2 >= 2 : false",
+        "1 < 3 < 2",
+        "fn f(x) { 0 <= x < 10 }; f(5) ? f(12) : true",
+        "fn half(x) { x % 2 == 0 ? Ok(x / 2) : Err(x) }; fn g(x) { Ok(half(x)? + 1) }; g(10)",
+        "fn half(x) { x % 2 == 0 ? Ok(x / 2) : Err(x) }; fn g(x) { Ok(half(x)? + 1) }; g(7)",
+        "fn f(x) { x < 0 ? throw x : x }; try { f(-1) } catch e { e - 1 }",
+        "try { 1 / 0 } catch e { e }",
+        "r = 0; try { throw 1 } catch e { r = e + 1 } finally { r = r * 10 }; r",
+        "fn f() { try { throw 1 } finally { 2 } }; try { f() } catch e { e + 5 }",
+        "x = nil; x?.y ?? 3",
+        "fn inc(n) { return n + 1; n }; inc(inc(1))",
+        "-(2 ** 62) * 4",
+        "x = 1; yield; fn f() { yield; 2 }; x + f()",
+    ];
+    for script in scripts {
+        let expected = outcome(&mut engine::new(), script);
+        assert!(expected.is_ok(), "{script}: {expected:?}");
+        assert_eq!(
+            outcome(&mut register::engine::new(), script),
+            expected,
+            "{script}"
+        );
+    }
+
+    let mut machine = register::engine::new();
+    let program = machine
+        .compile("x = 1; fn f(y) { y + yield }; x = x + f(yield) + 1; yield; x * 10")
+        .ok()
+        .expect("yielding script should compile");
+    machine.start(&program);
+    assert!(matches!(
+        machine.resume(&program),
+        Ok(tpc::vm::Status::Yielded)
+    ));
+    assert!(matches!(
+        machine.resume_with(&program, Value::Integer(4)),
+        Ok(tpc::vm::Status::Yielded)
+    ));
+    assert!(matches!(
+        machine.resume_with(&program, Value::Integer(8)),
+        Ok(tpc::vm::Status::Yielded)
+    ));
+    assert_eq!(machine.global("x"), Some(Value::Integer(14)));
+    assert!(matches!(
+        machine.resume(&program),
+        Ok(tpc::vm::Status::Finished(Value::Integer(140)))
+    ));
+
+    let failing = ["Ok(1)?", "1 / 0", "unknown", "fn f() { throw 7 }; f()"];
+    for script in failing {
+        let expected = outcome(&mut engine::new(), script);
+        assert!(expected.is_err(), "{script}");
+        assert_eq!(
+            outcome(&mut register::engine::new(), script),
+            expected,
+            "{script}"
+        );
+    }
+
+    let mut stack = engine::new();
+    let mut register = register::engine::new();
+    assert_eq!(outcome(&mut stack, scripts[5]), Ok(Value::Boolean(true)));
+    assert_eq!(outcome(&mut register, scripts[5]), Ok(Value::Boolean(true)));
+    assert_eq!(
+        outcome(&mut register, scripts[6]),
+        Ok(Value::Boolean(false))
+    );
+    assert_eq!(
+        outcome(&mut register, scripts[9]),
+        Ok(Value::Err(Value::Integer(7).into()))
+    );
+
+    let mut machine = register::engine::new();
+    assert!(matches!(
+        machine.eval("fn f(n) { f(n + 1) }; f(0)"),
+        Err(Error::Runtime(RuntimeError {
+            error: tpc::state::VMError::StackOverflow,
+            ..
+        }))
+    ));
+    machine.register_fn("twice", |x: i64| x * 2);
+    machine.set_global("base", Value::Integer(40));
+    assert!(matches!(
+        machine.eval("base + twice(1)"),
+        Ok(Value::Integer(42))
+    ));
+    assert!(matches!(
+        machine.eval("total = base + 2"),
+        Ok(Value::Integer(42))
+    ));
+    assert!(matches!(machine.global("total"), Some(Value::Integer(42))));
+}
+
+#[test]
+fn decode_test() {
+    use tpc::{
+        decode::{decode, Instruction},
+        handler::{Handler, HandlerTable},
+        opcode::*,
+        verify::VerifyError,
+    };
+
+    let mut handlers = HandlerTable::new();
+    handlers.push(Handler {
+        start: 0,
+        end: 3,
+        target: 9,
+        depth: 0,
+    });
+    let code = vec![LDI8, 5, LDT, CJP, GR, 0, 0, 0, 9, THR, END];
+    let decoded = decode(&code, &handlers).ok().unwrap();
+    assert_eq!(
+        decoded.code,
+        [
+            Instruction::Integer(5),
+            Instruction::True,
+            Instruction::CompareJump(GR, 3),
+            Instruction::Throw,
+            Instruction::End,
+        ]
+    );
+    assert_eq!(decoded.addresses, [0, 2, 3, 9, 10]);
+    let handler = decoded.handlers.find(1).unwrap();
+    assert_eq!((handler.start, handler.end, handler.target), (0, 2, 3));
+
+    let empty = HandlerTable::new();
+    assert_eq!(
+        decode(&vec![JMP, 0, 0, 0, 2, END], &empty).err(),
+        Some(VerifyError::InvalidTarget {
+            address: 0,
+            target: 2
+        })
+    );
+    assert_eq!(
+        decode(&vec![LDT, CJP, ADD, 0, 0, 0, 0], &empty).err(),
+        Some(VerifyError::UnknownOpcode {
+            address: 1,
+            opcode: ADD
+        })
+    );
+    assert_eq!(
+        decode(&vec![LDI16, 1], &empty).err(),
+        Some(VerifyError::MissingOperand { address: 0 })
+    );
+
+    let mut engine = engine::new();
+    let program = engine
+        .compile("fn f(x) { x.y }; try { f(1) } catch e { 1 / 0 }")
+        .ok()
+        .unwrap();
+    match engine.run(&program) {
+        Err(Error::Runtime(error)) => assert_eq!(error.pos, Some(42..43)),
+        _ => panic!("expected a runtime error"),
+    }
 }
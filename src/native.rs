@@ -0,0 +1,108 @@
+use std::rc::Rc;
+
+use crate::{
+    convert::IntoNative,
+    state::{Stack, State, VMResult},
+    value::Value,
+};
+
+pub type NativeFn<S> = dyn Fn(&mut State<S>, &[Value]) -> VMResult<Value>;
+
+#[derive(Clone, Copy)]
+pub struct Native {
+    pub index: u16,
+    pub arity: u8,
+}
+
+pub trait Natives {
+    fn find(&self, name: &str) -> Option<Native>;
+}
+
+impl Natives for () {
+    fn find(&self, _name: &str) -> Option<Native> {
+        None
+    }
+}
+
+struct Entry<S> {
+    name: Box<str>,
+    arity: u8,
+    function: Rc<NativeFn<S>>,
+}
+
+pub struct Registry<S> {
+    entries: Vec<Entry<S>>,
+}
+
+impl<S> Registry<S> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn register<F>(&mut self, name: &str, arity: u8, function: F) -> Native
+    where
+        F: Fn(&mut State<S>, &[Value]) -> VMResult<Value> + 'static,
+    {
+        let entry = Entry {
+            name: name.into(),
+            arity,
+            function: Rc::new(function),
+        };
+        let index = match self.entries.iter().position(|e| e.name == entry.name) {
+            Some(index) => {
+                self.entries[index] = entry;
+                index
+            }
+            None => {
+                self.entries.push(entry);
+                self.entries.len() - 1
+            }
+        };
+        Native {
+            index: index as u16,
+            arity,
+        }
+    }
+
+    pub fn name(&self, index: u16) -> Option<&str> {
+        self.entries.get(index as usize).map(|e| e.name.as_ref())
+    }
+
+    pub fn get(&self, index: u16) -> Option<(u8, Rc<NativeFn<S>>)> {
+        self.entries
+            .get(index as usize)
+            .map(|e| (e.arity, e.function.clone()))
+    }
+}
+
+impl<S: Stack> Registry<S> {
+    pub fn register_fn<Args, F>(&mut self, name: &str, function: F) -> Native
+    where
+        F: IntoNative<S, Args> + 'static,
+    {
+        let owned: Box<str> = name.into();
+        self.register(name, F::ARITY, move |state, arguments| {
+            function.call(state, &owned, arguments)
+        })
+    }
+}
+
+impl<S> Default for Registry<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Natives for Registry<S> {
+    fn find(&self, name: &str) -> Option<Native> {
+        self.entries
+            .iter()
+            .position(|e| e.name.as_ref() == name)
+            .map(|index| Native {
+                index: index as u16,
+                arity: self.entries[index].arity,
+            })
+    }
+}
@@ -1,27 +1,140 @@
+use crate::get::{GetByte, GetData};
+
+pub const VERSION: u16 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Byte,
+    Opcode,
+    Index,
+    Address,
+    Int8,
+    Int16,
+    Int32,
+    Integer,
+    Real,
+    Limbs,
+    Name,
+}
+
+impl Operand {
+    pub fn size<G: GetByte>(self, program: &G, address: usize) -> Option<usize> {
+        let size = match self {
+            Operand::Byte | Operand::Opcode | Operand::Int8 => 1,
+            Operand::Index | Operand::Int16 => 2,
+            Operand::Address | Operand::Int32 => 4,
+            Operand::Integer | Operand::Real => 8,
+            Operand::Limbs => 2 + 4 * GetData::<u16>::get_data(program, address)? as usize,
+            Operand::Name => 2 + GetData::<u16>::get_data(program, address)? as usize,
+        };
+        program.get_byte(address + size - 1)?;
+        Some(size)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Info {
+    pub name: &'static str,
+    pub operands: &'static [Operand],
+    pub pops: u8,
+    pub pushes: u8,
+}
+
+impl Info {
+    pub fn size<G: GetByte>(&self, program: &G, address: usize) -> Option<usize> {
+        let mut size = 1;
+        for operand in self.operands {
+            size += operand.size(program, address + size)?;
+        }
+        Some(size)
+    }
+}
+
 macro_rules! impl_opcodes {
-    ($($n:ident:$l:literal)*) => {
+    ($($n:ident:$l:literal [$($o:ident),*] $pops:literal $pushes:literal)*) => {
         $(pub const $n: u8 = $l;)*
+
+        pub fn info(opcode: u8) -> Option<Info> {
+            match opcode {
+                $($l => Some(Info {
+                    name: stringify!($n),
+                    operands: &[$(Operand::$o),*],
+                    pops: $pops,
+                    pushes: $pushes,
+                }),)*
+                _ => None,
+            }
+        }
+
+        pub fn find(name: &str) -> Option<u8> {
+            match name {
+                $(stringify!($n) => Some($l),)*
+                _ => None,
+            }
+        }
     };
 }
 
 impl_opcodes!(
-    END: 0x00
-    LDI: 0x01
-    ADD: 0x02
-    MUL: 0x03
-    SUB: 0x04
-    DIV: 0x05
-    LDR: 0x06
-    MOD: 0x07
-    EQ: 0x08
-    NE: 0x09
-    LS: 0x0A
-    GR: 0x0B
-    LE: 0x0C
-    GE: 0x0D
-    AND: 0x0E
-    OR: 0x0F
-    XOR: 0x10
-    SHL: 0x11
-    SHR: 0x12
+    END: 0x00 [] 1 0
+    LDI: 0x01 [Integer] 0 1
+    ADD: 0x02 [] 2 1
+    MUL: 0x03 [] 2 1
+    SUB: 0x04 [] 2 1
+    DIV: 0x05 [] 2 1
+    LDR: 0x06 [Real] 0 1
+    MOD: 0x07 [] 2 1
+    EQ: 0x08 [] 2 1
+    NE: 0x09 [] 2 1
+    LS: 0x0A [] 2 1
+    GR: 0x0B [] 2 1
+    LE: 0x0C [] 2 1
+    GE: 0x0D [] 2 1
+    AND: 0x0E [] 2 1
+    OR: 0x0F [] 2 1
+    XOR: 0x10 [] 2 1
+    SHL: 0x11 [] 2 1
+    SHR: 0x12 [] 2 1
+    POP: 0x13 [] 1 0
+    YLD: 0x14 [] 0 1
+    CLN: 0x15 [Index, Byte] 0 1
+    LDG: 0x16 [Index] 0 1
+    STG: 0x17 [Index] 1 1
+    LDB: 0x18 [Byte, Limbs] 0 1
+    POW: 0x19 [] 2 1
+    NEG: 0x1A [] 1 1
+    FDV: 0x1B [] 2 1
+    EMD: 0x1C [] 2 1
+    LDT: 0x1D [] 0 1
+    LDF: 0x1E [] 0 1
+    LDV: 0x1F [] 0 1
+    DUP: 0x20 [] 1 2
+    SWP: 0x21 [] 2 2
+    ROT: 0x22 [] 3 3
+    JMP: 0x23 [Address] 0 0
+    JFP: 0x24 [Address] 1 0
+    JNN: 0x25 [Address] 1 0
+    LDN: 0x26 [] 0 1
+    FLD: 0x27 [Name] 1 1
+    JIN: 0x28 [Address] 1 1
+    THR: 0x29 [] 1 0
+    CAL: 0x2A [Address, Byte] 0 1
+    RET: 0x2B [] 1 0
+    LDL: 0x2C [Byte] 0 1
+    STL: 0x2D [Byte] 1 1
+    LCL: 0x2E [Byte] 0 0
+    MKO: 0x2F [] 1 1
+    MKE: 0x30 [] 1 1
+    UNW: 0x31 [] 1 1
+    ADI: 0x32 [Int32] 1 1
+    SBI: 0x33 [Int32] 1 1
+    MLI: 0x34 [Int32] 1 1
+    CJP: 0x35 [Opcode, Address] 2 0
+    INL: 0x36 [Byte, Int32] 0 1
+    LD0: 0x37 [] 0 1
+    LD1: 0x38 [] 0 1
+    LDI8: 0x39 [Int8] 0 1
+    LDI16: 0x3A [Int16] 0 1
+    LDI32: 0x3B [Int32] 0 1
+    LDC: 0x3C [Index] 0 1
 );
@@ -0,0 +1,603 @@
+use crate::{
+    ast::*,
+    compiler::{CompileError, Stream},
+    token::*,
+};
+
+type ParseResult = Result<Node, CompileError>;
+
+struct Parser<'a, S> {
+    stream: &'a mut S,
+    pending: Option<TokenAndPos>,
+    end: usize,
+}
+
+impl<S: Stream> Parser<'_, S> {
+    fn peek(&mut self) -> Option<&TokenAndPos> {
+        match self.pending {
+            Some(ref token_and_pos) => Some(token_and_pos),
+            None => self.stream.peek(),
+        }
+    }
+
+    fn next(&mut self) -> Option<TokenAndPos> {
+        let token_and_pos = self.pending.take().or_else(|| self.stream.next());
+        if let Some(token_and_pos) = &token_and_pos {
+            self.end = token_and_pos.pos.end;
+        }
+        token_and_pos
+    }
+
+    fn node(&self, kind: Kind, start: usize) -> Node {
+        Node {
+            kind,
+            pos: start..self.end,
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Integer(value) => format!("integer '{value}'"),
+        Token::BigInteger(value) => format!("integer '{value}'"),
+        Token::Real(value) => format!("real '{value}'"),
+        Token::Single(c) => format!("character '{}'", *c as char),
+        Token::Double(c0, c1) => format!("token '{}{}'", *c0 as char, *c1 as char),
+        Token::Identifier(name) => format!("identifier '{name}'"),
+        Token::Error(_) => "invalid token".into(),
+    }
+}
+
+fn expect<S: Stream>(
+    parser: &mut Parser<S>,
+    expected: Token,
+    name: &str,
+) -> Result<Pos, CompileError> {
+    match parser.next() {
+        Some(token_and_pos) if token_and_pos.token == expected => Ok(token_and_pos.pos),
+        Some(token_and_pos) => Err(CompileError {
+            message: format!("Expected {name}, found {}.", describe(&token_and_pos.token)).into(),
+            pos: token_and_pos.pos,
+        }),
+        None => Err(CompileError {
+            message: format!("Expected {name}, found end of code.").into(),
+            pos: 0..0,
+        }),
+    }
+}
+
+fn is_next<S: Stream>(parser: &mut Parser<S>, token: Token) -> bool {
+    match parser.peek() {
+        Some(token_and_pos) => token_and_pos.token == token,
+        None => false,
+    }
+}
+
+fn is_keyword<S: Stream>(parser: &mut Parser<S>, name: &str) -> bool {
+    match parser.peek() {
+        Some(TokenAndPos {
+            token: Token::Identifier(identifier),
+            ..
+        }) => identifier.as_ref() == name,
+        _ => false,
+    }
+}
+
+fn identifier<S: Stream>(
+    parser: &mut Parser<S>,
+    what: &str,
+    pos: Pos,
+) -> Result<(Box<str>, Pos), CompileError> {
+    match parser.next() {
+        Some(TokenAndPos {
+            token: Token::Identifier(name),
+            pos,
+        }) => Ok((name, pos)),
+        Some(token_and_pos) => Err(CompileError {
+            message: format!("Expected {what}, found {}.", describe(&token_and_pos.token)).into(),
+            pos: token_and_pos.pos,
+        }),
+        None => Err(CompileError {
+            message: format!("Expected {what}, found end of code.").into(),
+            pos,
+        }),
+    }
+}
+
+fn can_start_expression(token: &Token) -> bool {
+    match token {
+        Token::Integer(_)
+        | Token::BigInteger(_)
+        | Token::Real(_)
+        | Token::Identifier(_)
+        | Token::Error(_) => true,
+        Token::Single(c) => matches!(c, b'(' | b'-'),
+        Token::Double(..) => false,
+    }
+}
+
+fn arguments<S: Stream>(parser: &mut Parser<S>) -> Result<Vec<Node>, CompileError> {
+    expect(parser, Token::Single(b'('), "'('")?;
+    let mut arguments = Vec::new();
+    if !is_next(parser, Token::Single(b')')) {
+        loop {
+            arguments.push(expression(parser)?);
+            if is_next(parser, Token::Single(b',')) {
+                parser.next();
+            } else {
+                break;
+            }
+        }
+    }
+    expect(parser, Token::Single(b')'), "')'")?;
+    Ok(arguments)
+}
+
+fn block<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    let start = expect(parser, Token::Single(b'{'), "'{'")?;
+    if is_next(parser, Token::Single(b'}')) {
+        parser.next();
+        return Ok(parser.node(Kind::Void, start.start));
+    }
+    let node = sequence(parser)?;
+    expect(parser, Token::Single(b'}'), "'}'")?;
+    Ok(node)
+}
+
+fn function<S: Stream>(parser: &mut Parser<S>, pos: Pos) -> ParseResult {
+    let (name, name_pos) = identifier(parser, "function name", pos.clone())?;
+    expect(parser, Token::Single(b'('), "'('")?;
+    let mut parameters: Vec<Box<str>> = Vec::new();
+    if !is_next(parser, Token::Single(b')')) {
+        loop {
+            let (parameter, parameter_pos) = identifier(parser, "parameter name", pos.clone())?;
+            if parameters.contains(&parameter) || parameters.len() == u8::MAX as usize {
+                return Err(CompileError {
+                    message: format!("Unable to define parameter '{parameter}'.").into(),
+                    pos: parameter_pos,
+                });
+            }
+            parameters.push(parameter);
+            if is_next(parser, Token::Single(b',')) {
+                parser.next();
+            } else {
+                break;
+            }
+        }
+    }
+    expect(parser, Token::Single(b')'), "')'")?;
+    let body = Box::new(block(parser)?);
+    Ok(parser.node(
+        Kind::Function {
+            name,
+            name_pos,
+            parameters,
+            body,
+        },
+        pos.start,
+    ))
+}
+
+fn try_catch<S: Stream>(parser: &mut Parser<S>, keyword_pos: Pos) -> ParseResult {
+    let body = Box::new(block(parser)?);
+    let catch = if is_keyword(parser, "catch") {
+        parser.next();
+        let (name, name_pos) = identifier(parser, "error variable name", keyword_pos.clone())?;
+        let body = Box::new(block(parser)?);
+        Some(Catch {
+            name,
+            name_pos,
+            body,
+        })
+    } else {
+        None
+    };
+    let finally = if is_keyword(parser, "finally") {
+        parser.next();
+        Some(Box::new(block(parser)?))
+    } else {
+        None
+    };
+    if catch.is_none() && finally.is_none() {
+        return Err(CompileError {
+            message: "Expected 'catch' or 'finally' after 'try' block.".into(),
+            pos: keyword_pos,
+        });
+    }
+    let start = keyword_pos.start;
+    Ok(parser.node(
+        Kind::Try {
+            keyword_pos,
+            body,
+            catch,
+            finally,
+        },
+        start,
+    ))
+}
+
+fn primary<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    let token_and_pos = match parser.next() {
+        Some(token_and_pos) => token_and_pos,
+        None => {
+            return Err(CompileError {
+                message: "Unexpected end of code.".into(),
+                pos: 0..0,
+            })
+        }
+    };
+    let start = token_and_pos.pos.start;
+    let kind = match token_and_pos.token {
+        Token::Integer(value) => Kind::Integer(value),
+        Token::Real(value) => Kind::Real(value),
+        Token::BigInteger(value) => Kind::BigInteger(value),
+        Token::Error(message) => {
+            return Err(CompileError {
+                message: String::from(message).into(),
+                pos: token_and_pos.pos,
+            })
+        }
+        Token::Single(b'(') => {
+            if is_next(parser, Token::Single(b')')) {
+                parser.next();
+                return Ok(parser.node(Kind::Void, start));
+            }
+            let node = expression(parser)?;
+            expect(parser, Token::Single(b')'), "')'")?;
+            return Ok(node);
+        }
+        Token::Identifier(name) => match name.as_ref() {
+            "yield" => Kind::Yield,
+            "true" => Kind::Boolean(true),
+            "false" => Kind::Boolean(false),
+            "nil" => Kind::Nil,
+            "throw" => Kind::Throw(Box::new(expression(parser)?)),
+            "return" => Kind::Return(Box::new(expression(parser)?)),
+            "try" => return try_catch(parser, token_and_pos.pos),
+            "fn" => return function(parser, token_and_pos.pos),
+            "Ok" | "Err" if is_next(parser, Token::Single(b'(')) => {
+                parser.next();
+                let value = Box::new(expression(parser)?);
+                expect(parser, Token::Single(b')'), "')'")?;
+                Kind::Result {
+                    ok: name.as_ref() == "Ok",
+                    value,
+                }
+            }
+            _ if is_next(parser, Token::Single(b'(')) => Kind::Call {
+                name,
+                arguments: arguments(parser)?,
+            },
+            _ if is_next(parser, Token::Single(b'=')) => {
+                parser.next();
+                Kind::Assign {
+                    name,
+                    name_pos: token_and_pos.pos,
+                    value: Box::new(expression(parser)?),
+                }
+            }
+            _ => Kind::Variable(name),
+        },
+        token => {
+            return Err(CompileError {
+                message: format!("Expected value, found {}.", describe(&token)).into(),
+                pos: token_and_pos.pos,
+            })
+        }
+    };
+    Ok(parser.node(kind, start))
+}
+
+fn postfix<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    let mut node = primary(parser)?;
+    while let Some(token_and_pos) = parser.peek() {
+        let start = node.pos.start;
+        let safe = match token_and_pos.token {
+            Token::Single(b'.') => false,
+            Token::Double(b'?', b'.') => true,
+            Token::Single(b'?') => {
+                let attached = token_and_pos.pos.start == node.pos.end;
+                let question = parser.next();
+                if parser.peek().is_some_and(|next| match next.token {
+                    Token::Single(b'-') => !attached,
+                    ref token => can_start_expression(token),
+                }) {
+                    parser.pending = question;
+                    break;
+                }
+                if let Some(question) = question {
+                    let kind = Kind::Unwrap {
+                        operand: Box::new(node),
+                        operator_pos: question.pos,
+                    };
+                    node = parser.node(kind, start);
+                }
+                continue;
+            }
+            _ => break,
+        };
+        let pos = token_and_pos.pos.clone();
+        parser.next();
+        let name = match parser.next() {
+            Some(TokenAndPos {
+                token: Token::Identifier(name),
+                ..
+            }) => name,
+            Some(token_and_pos) => {
+                return Err(CompileError {
+                    message: format!(
+                        "Expected field name, found {}.",
+                        describe(&token_and_pos.token)
+                    )
+                    .into(),
+                    pos: token_and_pos.pos,
+                })
+            }
+            None => {
+                return Err(CompileError {
+                    message: "Expected field name, found end of code.".into(),
+                    pos,
+                })
+            }
+        };
+        let access_pos = pos.start..parser.end;
+        let kind = if is_next(parser, Token::Single(b'(')) {
+            Kind::Method {
+                receiver: Box::new(node),
+                name,
+                arguments: arguments(parser)?,
+                access_pos,
+                safe,
+            }
+        } else {
+            Kind::Field {
+                object: Box::new(node),
+                name,
+                access_pos,
+                safe,
+            }
+        };
+        node = parser.node(kind, start);
+    }
+    Ok(node)
+}
+
+fn binary_node<S: Stream>(
+    parser: &Parser<S>,
+    operator: BinaryOperator,
+    operator_pos: Pos,
+    left: Node,
+    right: Node,
+) -> Node {
+    let start = left.pos.start;
+    parser.node(
+        Kind::Binary {
+            operator,
+            operator_pos,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+        start,
+    )
+}
+
+fn multiple_binary_helper<S: Stream, N, M>(
+    parser: &mut Parser<S>,
+    next: N,
+    mapper: M,
+) -> ParseResult
+where
+    N: Fn(&mut Parser<S>) -> ParseResult,
+    M: Fn(&Token) -> Option<BinaryOperator>,
+{
+    let mut node = next(parser)?;
+    while let Some(token_and_pos) = parser.peek() {
+        if let Some(operator) = mapper(&token_and_pos.token) {
+            let pos = token_and_pos.pos.clone();
+            parser.next();
+            let right = next(parser)?;
+            node = binary_node(parser, operator, pos, node, right);
+        } else {
+            break;
+        }
+    }
+    Ok(node)
+}
+
+fn power<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    let node = postfix(parser)?;
+    if let Some(token_and_pos) = parser.peek() {
+        if token_and_pos.token == Token::Double(b'*', b'*') {
+            let pos = token_and_pos.pos.clone();
+            parser.next();
+            let right = unary(parser)?;
+            return Ok(binary_node(parser, BinaryOperator::Power, pos, node, right));
+        }
+    }
+    Ok(node)
+}
+
+fn unary<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    if let Some(token_and_pos) = parser.peek() {
+        if token_and_pos.token == Token::Single(b'-') {
+            let pos = token_and_pos.pos.clone();
+            parser.next();
+            let operand = Box::new(unary(parser)?);
+            let start = pos.start;
+            return Ok(parser.node(
+                Kind::Negate {
+                    operand,
+                    operator_pos: pos,
+                },
+                start,
+            ));
+        }
+    }
+    power(parser)
+}
+
+fn factor<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    multiple_binary_helper(parser, unary, |token| match token {
+        Token::Single(b'*') => Some(BinaryOperator::Multiply),
+        Token::Single(b'/') => Some(BinaryOperator::Divide),
+        Token::Single(b'%') => Some(BinaryOperator::Modulo),
+        Token::Double(b'/', b'/') => Some(BinaryOperator::FloorDivide),
+        Token::Double(b'%', b'%') => Some(BinaryOperator::EuclidModulo),
+        _ => None,
+    })
+}
+
+fn term<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    multiple_binary_helper(parser, factor, |token| match token {
+        Token::Single(b'+') => Some(BinaryOperator::Add),
+        Token::Single(b'-') => Some(BinaryOperator::Subtract),
+        _ => None,
+    })
+}
+
+fn shifts<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    multiple_binary_helper(parser, term, |token| match token {
+        Token::Double(b'<', b'<') => Some(BinaryOperator::ShiftLeft),
+        Token::Double(b'>', b'>') => Some(BinaryOperator::ShiftRight),
+        _ => None,
+    })
+}
+
+fn and<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    multiple_binary_helper(parser, shifts, |token| match token {
+        Token::Single(b'&') => Some(BinaryOperator::And),
+        _ => None,
+    })
+}
+
+fn xor<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    multiple_binary_helper(parser, and, |token| match token {
+        Token::Single(b'^') => Some(BinaryOperator::Xor),
+        _ => None,
+    })
+}
+
+fn or<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    multiple_binary_helper(parser, xor, |token| match token {
+        Token::Single(b'|') => Some(BinaryOperator::Or),
+        _ => None,
+    })
+}
+
+fn comparison_operator<S: Stream>(parser: &mut Parser<S>) -> Option<(ComparisonOperator, Pos)> {
+    let token_and_pos = parser.peek()?;
+    let operator = match token_and_pos.token {
+        Token::Single(b'<') => ComparisonOperator::Less,
+        Token::Single(b'>') => ComparisonOperator::Greater,
+        Token::Double(b'<', b'=') => ComparisonOperator::LessEqual,
+        Token::Double(b'>', b'=') => ComparisonOperator::GreaterEqual,
+        Token::Double(b'=', b'=') => ComparisonOperator::Equal,
+        Token::Double(b'!', b'=') => ComparisonOperator::NotEqual,
+        _ => return None,
+    };
+    Some((operator, parser.next()?.pos))
+}
+
+fn comparison<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    let first = or(parser)?;
+    let mut rest = Vec::new();
+    while let Some((operator, pos)) = comparison_operator(parser) {
+        rest.push((operator, pos, or(parser)?));
+    }
+    if rest.is_empty() {
+        return Ok(first);
+    }
+    let start = first.pos.start;
+    Ok(parser.node(
+        Kind::Comparison {
+            first: Box::new(first),
+            rest,
+        },
+        start,
+    ))
+}
+
+fn coalesce<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    let mut node = comparison(parser)?;
+    while is_next(parser, Token::Double(b'?', b'?')) {
+        if let Some(token_and_pos) = parser.next() {
+            let right = comparison(parser)?;
+            let start = node.pos.start;
+            node = parser.node(
+                Kind::Coalesce {
+                    operator_pos: token_and_pos.pos,
+                    left: Box::new(node),
+                    right: Box::new(right),
+                },
+                start,
+            );
+        }
+    }
+    Ok(node)
+}
+
+fn conditional<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    let condition = coalesce(parser)?;
+    if is_next(parser, Token::Single(b'?')) {
+        if let Some(token_and_pos) = parser.next() {
+            let then = Box::new(conditional(parser)?);
+            let colon_pos = expect(parser, Token::Single(b':'), "':'")?;
+            let otherwise = Box::new(conditional(parser)?);
+            let start = condition.pos.start;
+            return Ok(parser.node(
+                Kind::Conditional {
+                    condition: Box::new(condition),
+                    question_pos: token_and_pos.pos,
+                    then,
+                    colon_pos,
+                    otherwise,
+                },
+                start,
+            ));
+        }
+    }
+    Ok(condition)
+}
+
+fn expression<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    conditional(parser)
+}
+
+fn sequence<S: Stream>(parser: &mut Parser<S>) -> ParseResult {
+    let first = expression(parser)?;
+    if !is_next(parser, Token::Single(b';')) {
+        return Ok(first);
+    }
+    let start = first.pos.start;
+    let mut nodes = vec![first];
+    while is_next(parser, Token::Single(b';')) {
+        parser.next();
+        nodes.push(expression(parser)?);
+    }
+    Ok(parser.node(Kind::Sequence(nodes), start))
+}
+
+pub fn parse<S: Stream>(stream: &mut S) -> Result<Option<Node>, CompileError> {
+    let mut parser = Parser {
+        stream,
+        pending: None,
+        end: 0,
+    };
+
+    let node = match parser.peek() {
+        Some(_) => Some(sequence(&mut parser)?),
+        None => None,
+    };
+
+    match parser.next() {
+        Some(token_and_pos) => Err(CompileError {
+            message: format!(
+                "Expected end of code, found {}.",
+                describe(&token_and_pos.token)
+            )
+            .into(),
+            pos: token_and_pos.pos,
+        }),
+        None => Ok(node),
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{
+    compiler::Tables,
+    get::{GetByte, GetData},
+    handler::{Handler, HandlerTable},
+    line::LineTable,
+    opcode::*,
+    token::Pos,
+};
+
+struct Instruction {
+    bytes: Vec<u8>,
+    targets: Vec<(usize, usize)>,
+    origins: Vec<usize>,
+    pos: Option<Pos>,
+}
+
+impl Instruction {
+    fn opcode(&self) -> u8 {
+        self.bytes[0]
+    }
+}
+
+fn decode<G: GetByte>(code: &G, lines: &LineTable) -> Option<Vec<Instruction>> {
+    let mut instructions = Vec::new();
+    let mut address = 0;
+    while let Some(opcode) = code.get_byte(address) {
+        let info = info(opcode)?;
+        let size = info.size(code, address)?;
+        let mut targets = Vec::new();
+        let mut offset = 1;
+        for operand in info.operands {
+            if *operand == Operand::Address {
+                let target: u32 = code.get_data(address + offset)?;
+                targets.push((offset, target as usize));
+            }
+            offset += operand.size(code, address + offset)?;
+        }
+        instructions.push(Instruction {
+            bytes: (address..address + size)
+                .map(|address| code.get_byte(address))
+                .collect::<Option<_>>()?,
+            targets,
+            origins: vec![address],
+            pos: lines.find(address),
+        });
+        address += size;
+    }
+    Some(instructions)
+}
+
+fn integer(instruction: &Instruction) -> Option<i64> {
+    let operand = &instruction.bytes[1..];
+    match instruction.opcode() {
+        LD0 => Some(0),
+        LD1 => Some(1),
+        LDI8 => Some(i8::from_be_bytes(operand.try_into().ok()?) as i64),
+        LDI16 => Some(i16::from_be_bytes(operand.try_into().ok()?) as i64),
+        LDI32 => Some(i32::from_be_bytes(operand.try_into().ok()?) as i64),
+        LDI => Some(i64::from_be_bytes(operand.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn is_label(instruction: &Instruction, labels: &[bool]) -> bool {
+    instruction.origins.iter().any(|&address| labels[address])
+}
+
+fn merge(instructions: Vec<Instruction>, bytes: Vec<u8>, pos: Option<Pos>) -> Instruction {
+    let mut targets = Vec::new();
+    let mut origins = Vec::new();
+    for instruction in instructions {
+        targets.extend(instruction.targets);
+        origins.extend(instruction.origins);
+    }
+    Instruction {
+        bytes,
+        targets,
+        origins,
+        pos,
+    }
+}
+
+fn reduce(output: &mut Vec<Instruction>, labels: &[bool], carry: &mut Vec<usize>) -> bool {
+    let count = output.len();
+    if count < 2 || is_label(&output[count - 1], labels) {
+        return false;
+    }
+    let (previous, last) = (&output[count - 2], &output[count - 1]);
+    let fused = match (previous.opcode(), last.opcode()) {
+        (
+            LDI | LD0 | LD1 | LDI8 | LDI16 | LDI32 | LDR | LDC | LDB | LDT | LDF | LDV | LDN | LDG
+            | LDL | DUP,
+            POP,
+        ) => {
+            for instruction in output.drain(count - 2..) {
+                carry.extend(instruction.origins);
+            }
+            return true;
+        }
+        (_, ADD | SUB | MUL) => {
+            let value = match integer(previous).map(i32::try_from) {
+                Some(Ok(value)) => value,
+                _ => return false,
+            };
+            let opcode = match last.opcode() {
+                ADD => ADI,
+                SUB => SBI,
+                _ => MLI,
+            };
+            let mut bytes = vec![opcode];
+            bytes.extend_from_slice(&value.to_be_bytes());
+            (2, bytes, last.pos.clone())
+        }
+        (LS | GR | LE | GE | EQ | NE, JFP) => {
+            let mut bytes = vec![CJP, previous.opcode()];
+            bytes.extend_from_slice(&last.bytes[1..]);
+            let pos = previous.pos.clone();
+            let mut instructions: Vec<_> = output.drain(count - 2..).collect();
+            for (offset, _) in &mut instructions[1].targets {
+                *offset += 1;
+            }
+            output.push(merge(instructions, bytes, pos));
+            return true;
+        }
+        (ADI, STL) if count >= 3 => {
+            let load = &output[count - 3];
+            if load.opcode() != LDL || load.bytes[1] != last.bytes[1] || is_label(previous, labels)
+            {
+                return false;
+            }
+            let mut bytes = vec![INL, last.bytes[1]];
+            bytes.extend_from_slice(&previous.bytes[1..]);
+            (3, bytes, previous.pos.clone())
+        }
+        _ => return false,
+    };
+    let (length, bytes, pos) = fused;
+    let instructions = output.drain(count - length..).collect();
+    output.push(merge(instructions, bytes, pos));
+    true
+}
+
+fn thread(jumps: &[Option<usize>], mut target: usize) -> usize {
+    for _ in 0..jumps.len() {
+        match jumps.get(target) {
+            Some(Some(next)) if *next != target => target = *next,
+            _ => break,
+        }
+    }
+    target
+}
+
+fn rewrite(code: &Vec<u8>, tables: &Tables) -> Option<(Vec<u8>, Tables)> {
+    let instructions = decode(code, &tables.lines)?;
+
+    let mut labels = vec![false; code.len() + 1];
+    let mut jumps = vec![None; code.len() + 1];
+    labels[0] = true;
+    for instruction in &instructions {
+        for &(_, target) in &instruction.targets {
+            *labels.get_mut(target)? = true;
+        }
+        if let (JMP, Some(&(_, target))) = (instruction.opcode(), instruction.targets.first()) {
+            jumps[instruction.origins[0]] = Some(target);
+        }
+    }
+    for handler in tables.handlers.iter() {
+        for address in [handler.start, handler.end, handler.target] {
+            *labels.get_mut(address)? = true;
+        }
+    }
+    for function in &tables.functions {
+        *labels.get_mut(function.address)? = true;
+    }
+
+    let mut output: Vec<Instruction> = Vec::with_capacity(instructions.len());
+    let mut carry = Vec::new();
+    for mut instruction in instructions {
+        instruction.origins.splice(0..0, carry.drain(..));
+        output.push(instruction);
+        while reduce(&mut output, &labels, &mut carry) {}
+    }
+
+    let mut addresses = vec![None; code.len() + 1];
+    let mut address = 0;
+    for instruction in &output {
+        for &origin in &instruction.origins {
+            addresses[origin] = Some(address);
+        }
+        address += instruction.bytes.len();
+    }
+    for origin in carry {
+        addresses[origin] = Some(address);
+    }
+    addresses[code.len()] = Some(address);
+    let map = |address: usize| *addresses.get(address)?;
+
+    let mut result = Vec::with_capacity(address);
+    let mut lines = LineTable::new();
+    for mut instruction in output {
+        for &(offset, target) in &instruction.targets {
+            let target = match instruction.opcode() {
+                CAL => target,
+                _ => thread(&jumps, target),
+            };
+            let bytes = (map(target)? as u32).to_be_bytes();
+            instruction.bytes[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+        if let Some(pos) = instruction.pos {
+            lines.push(result.len(), pos);
+        }
+        result.extend_from_slice(&instruction.bytes);
+    }
+
+    let mut handlers = HandlerTable::new();
+    for handler in tables.handlers.iter() {
+        handlers.push(Handler {
+            start: map(handler.start)?,
+            end: map(handler.end)?,
+            target: map(handler.target)?,
+            depth: handler.depth,
+        });
+    }
+    let mut functions = tables.functions.clone();
+    for function in &mut functions {
+        function.address = map(function.address)?;
+    }
+    Some((
+        result,
+        Tables {
+            lines,
+            handlers,
+            functions,
+            constants: Vec::new(),
+            warnings: Vec::new(),
+        },
+    ))
+}
+
+pub fn optimize(code: Vec<u8>, tables: Tables) -> (Vec<u8>, Tables) {
+    match rewrite(&code, &tables) {
+        Some((code, optimized)) => (
+            code,
+            Tables {
+                constants: tables.constants,
+                warnings: tables.warnings,
+                ..optimized
+            },
+        ),
+        None => (code, tables),
+    }
+}
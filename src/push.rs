@@ -4,6 +4,22 @@ pub trait PushByte {
     fn push_byte(&mut self, value: u8);
 }
 
+impl PushByte for Vec<u8> {
+    fn push_byte(&mut self, value: u8) {
+        self.push(value)
+    }
+}
+
+pub trait PatchByte {
+    fn patch_byte(&mut self, address: usize, value: u8);
+}
+
+impl PatchByte for Vec<u8> {
+    fn patch_byte(&mut self, address: usize, value: u8) {
+        self[address] = value
+    }
+}
+
 pub trait IntoGetByte {
     type Target: GetByte;
     fn into_get_byte(self) -> Self::Target;
@@ -13,6 +29,10 @@ pub trait PushData<T> {
     fn push_data(&mut self, value: T);
 }
 
+pub trait PatchData<T> {
+    fn patch_data(&mut self, address: usize, value: T);
+}
+
 macro_rules! impl_push_data {
     ($($t:ty),*) => {
         $(
@@ -23,8 +43,16 @@ macro_rules! impl_push_data {
                     }
                 }
             }
+
+            impl<P: PatchByte> PatchData<$t> for P {
+                fn patch_data(&mut self, address: usize, value: $t) {
+                    for (i, b) in value.to_be_bytes().iter().cloned().enumerate() {
+                        self.patch_byte(address + i, b);
+                    }
+                }
+            }
         )*
     };
 }
 
-impl_push_data!(i64, f64);
+impl_push_data!(u8, u16, u32, i8, i16, i32, i64, f64);
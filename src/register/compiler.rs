@@ -0,0 +1,822 @@
+use crate::{
+    ast::*,
+    compiler::{binary_opcode, comparison_opcode, CompileError, Globals, Stream},
+    fold, handler,
+    native::Natives,
+    parser,
+    token::Pos,
+    value::Value,
+};
+
+use super::{Function, Handler, Instruction, Program, Register};
+
+type CompileResult = Result<(), CompileError>;
+
+struct Context<'a> {
+    natives: &'a dyn Natives,
+    globals: &'a mut dyn Globals,
+    code: Vec<Instruction>,
+    lines: Vec<Pos>,
+    handlers: Vec<Handler>,
+    functions: Vec<Function>,
+    bodies: Vec<Pos>,
+    finally: usize,
+    locals: Option<Vec<Box<str>>>,
+    top: usize,
+    registers: usize,
+}
+
+impl Context<'_> {
+    fn emit(&mut self, instruction: Instruction, pos: Pos) -> usize {
+        self.code.push(instruction);
+        self.lines.push(pos);
+        self.code.len() - 1
+    }
+
+    fn patch(&mut self, address: usize) {
+        let offset = self.code.len();
+        match &mut self.code[address] {
+            Instruction::Jump { target }
+            | Instruction::JumpIfFalse { target, .. }
+            | Instruction::JumpIfNil { target, .. }
+            | Instruction::JumpIfNotNil { target, .. } => *target = offset,
+            _ => {}
+        }
+    }
+
+    fn temp(&mut self) -> Register {
+        let register = self.top;
+        self.top += 1;
+        self.registers = self.registers.max(self.top);
+        register as Register
+    }
+
+    fn local(&self, name: &str) -> Option<Register> {
+        self.locals
+            .as_ref()
+            .and_then(|locals| locals.iter().position(|local| local.as_ref() == name))
+            .map(|index| index as Register)
+    }
+}
+
+fn children(node: &Node, f: &mut dyn FnMut(&Node)) {
+    match &node.kind {
+        Kind::Assign { value, .. }
+        | Kind::Field { object: value, .. }
+        | Kind::Unwrap { operand: value, .. }
+        | Kind::Negate { operand: value, .. }
+        | Kind::Result { value, .. }
+        | Kind::Throw(value)
+        | Kind::Return(value) => f(value),
+        Kind::Call { arguments, .. } => arguments.iter().for_each(f),
+        Kind::Method {
+            receiver,
+            arguments,
+            ..
+        } => {
+            f(receiver);
+            arguments.iter().for_each(f);
+        }
+        Kind::Binary { left, right, .. } | Kind::Coalesce { left, right, .. } => {
+            f(left);
+            f(right);
+        }
+        Kind::Comparison { first, rest } => {
+            f(first);
+            rest.iter().for_each(|(_, _, node)| f(node));
+        }
+        Kind::Conditional {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => {
+            f(condition);
+            f(then);
+            f(otherwise);
+        }
+        Kind::Sequence(nodes) => nodes.iter().for_each(f),
+        Kind::Try {
+            body,
+            catch,
+            finally,
+            ..
+        } => {
+            f(body);
+            if let Some(catch) = catch {
+                f(&catch.body);
+            }
+            if let Some(finally) = finally {
+                f(finally);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn assigned(node: &Node, found: &mut dyn FnMut(&str)) {
+    match &node.kind {
+        Kind::Assign { name, .. } => found(name),
+        Kind::Try {
+            catch: Some(catch), ..
+        } => found(&catch.name),
+        _ => {}
+    }
+    children(node, &mut |child| assigned(child, &mut *found));
+}
+
+fn assigns(node: &Node, name: &str) -> bool {
+    let mut result = false;
+    assigned(node, &mut |found| result |= found == name);
+    result
+}
+
+fn operand(context: &mut Context, node: &Node, later: &[&Node]) -> Result<Register, CompileError> {
+    if let Kind::Variable(name) = &node.kind {
+        if let Some(register) = context.local(name) {
+            if !later.iter().any(|node| assigns(node, name)) {
+                return Ok(register);
+            }
+        }
+    }
+    let register = context.temp();
+    generate(context, node, register)?;
+    Ok(register)
+}
+
+enum Variable {
+    Local(Register),
+    Global(u16),
+}
+
+fn define(context: &mut Context, name: &str, pos: Pos) -> Result<Variable, CompileError> {
+    if let Some(locals) = &mut context.locals {
+        if let Some(index) = locals.iter().position(|local| local.as_ref() == name) {
+            return Ok(Variable::Local(index as Register));
+        }
+        if locals.len() < u8::MAX as usize {
+            locals.push(name.into());
+            return Ok(Variable::Local((locals.len() - 1) as Register));
+        }
+        return Err(CompileError {
+            message: format!("Unable to define local variable '{name}'.").into(),
+            pos,
+        });
+    }
+    match context.globals.define(name) {
+        Some(index) => Ok(Variable::Global(index)),
+        None => Err(CompileError {
+            message: format!("Unable to define global variable '{name}'.").into(),
+            pos,
+        }),
+    }
+}
+
+fn variable(context: &mut Context, name: &str, dest: Register, pos: Pos) -> CompileResult {
+    if let Some(source) = context.local(name) {
+        context.emit(Instruction::Move { dest, source }, pos);
+        return Ok(());
+    }
+    match context.globals.find(name) {
+        Some(index) => {
+            context.emit(Instruction::LoadGlobal { dest, index }, pos);
+            Ok(())
+        }
+        None => Err(CompileError {
+            message: format!("Unknown name '{name}'.").into(),
+            pos,
+        }),
+    }
+}
+
+fn call_native(
+    context: &mut Context,
+    name: &str,
+    name_pos: Pos,
+    pos: Pos,
+    arguments: &[Node],
+    dest: Register,
+    mut count: usize,
+) -> CompileResult {
+    let native = match context.natives.find(name) {
+        Some(native) => native,
+        None => {
+            return Err(CompileError {
+                message: format!("Unknown native function '{name}'.").into(),
+                pos: name_pos,
+            })
+        }
+    };
+    let first = (context.top - count) as Register;
+    for argument in arguments {
+        let register = context.temp();
+        generate(context, argument, register)?;
+        count += 1;
+    }
+    if count > u8::MAX as usize || count != native.arity as usize {
+        return Err(CompileError {
+            message: format!(
+                "Native function '{name}' expects {} argument(s), found {count}.",
+                native.arity
+            )
+            .into(),
+            pos,
+        });
+    }
+    context.emit(
+        Instruction::CallNative {
+            dest,
+            index: native.index,
+            first,
+            count: count as u8,
+        },
+        pos,
+    );
+    Ok(())
+}
+
+fn call_function(
+    context: &mut Context,
+    index: usize,
+    arguments: &[Node],
+    dest: Register,
+    pos: Pos,
+) -> CompileResult {
+    let top = context.top;
+    for argument in arguments {
+        let register = context.temp();
+        generate(context, argument, register)?;
+    }
+    let count = arguments.len();
+    let function = &context.functions[index];
+    if count != function.arity as usize {
+        return Err(CompileError {
+            message: format!(
+                "Function '{}' expects {} argument(s), found {count}.",
+                function.name, function.arity
+            )
+            .into(),
+            pos,
+        });
+    }
+    context.emit(
+        Instruction::Call {
+            dest,
+            function: index,
+            first: top as Register,
+            count: count as u8,
+        },
+        pos,
+    );
+    context.top = top;
+    Ok(())
+}
+
+fn function(
+    context: &mut Context,
+    name: &str,
+    name_pos: Pos,
+    parameters: &[Box<str>],
+    body: &Node,
+    dest: Register,
+    pos: Pos,
+) -> CompileResult {
+    let skip = context.emit(Instruction::Jump { target: 0 }, name_pos.clone());
+    let index = context.functions.len();
+    context.functions.push(Function {
+        name: name.into(),
+        address: context.code.len(),
+        arity: parameters.len() as u8,
+        registers: 0,
+    });
+    let mut names = parameters.to_vec();
+    assigned(body, &mut |name| {
+        if !names.iter().any(|n| n.as_ref() == name) {
+            names.push(name.into());
+        }
+    });
+    let locals = context.locals.replace(parameters.to_vec());
+    let top = core::mem::replace(&mut context.top, names.len());
+    let registers = core::mem::replace(&mut context.registers, names.len());
+    let finally = core::mem::replace(&mut context.finally, 0);
+    let result = context.temp();
+    generate(context, body, result)?;
+    context.emit(Instruction::Return { source: result }, name_pos.clone());
+    if context.registers > u16::MAX as usize {
+        return Err(CompileError {
+            message: format!("Function '{name}' uses too many registers.").into(),
+            pos: name_pos,
+        });
+    }
+    context.functions[index].registers = context.registers as u16;
+    context.locals = locals;
+    context.top = top;
+    context.registers = registers;
+    context.finally = finally;
+    context
+        .bodies
+        .push(context.functions[index].address..context.code.len());
+    context.patch(skip);
+    context.emit(
+        Instruction::Load {
+            dest,
+            value: Value::Void,
+        },
+        pos.start..name_pos.end,
+    );
+    Ok(())
+}
+
+fn early_return(context: &mut Context, instruction: Instruction, pos: Pos) -> CompileResult {
+    let name = match instruction {
+        Instruction::Return { .. } => "return",
+        _ => "?",
+    };
+    if context.locals.is_none() {
+        return Err(CompileError {
+            message: format!("Unable to use '{name}' outside of a function body.").into(),
+            pos,
+        });
+    }
+    if context.finally != 0 {
+        return Err(CompileError {
+            message: format!("Unable to use '{name}' inside a 'try' with a 'finally' block.")
+                .into(),
+            pos,
+        });
+    }
+    context.emit(instruction, pos);
+    Ok(())
+}
+
+fn try_catch(
+    context: &mut Context,
+    body: &Node,
+    catch: Option<&Catch>,
+    finally: Option<&Node>,
+    dest: Register,
+    pos: Pos,
+) -> CompileResult {
+    let top = context.top;
+    let guarded = finally.is_some() as usize;
+    context.finally += guarded;
+    let start = context.code.len();
+    generate(context, body, dest)?;
+    let body_end = context.code.len();
+    let mut ends = vec![context.emit(Instruction::Jump { target: 0 }, pos.clone())];
+    let (error, flag) = match finally {
+        Some(_) => (context.temp(), context.temp()),
+        None => (dest, dest),
+    };
+    let mut handlers = Vec::new();
+    if let Some(catch) = catch {
+        let variable = define(context, &catch.name, catch.name_pos.clone())?;
+        let catch_start = context.code.len();
+        match variable {
+            Variable::Local(register) => handlers.push((start, body_end, catch_start, register)),
+            Variable::Global(index) => {
+                handlers.push((start, body_end, catch_start, dest));
+                context.emit(
+                    Instruction::StoreGlobal {
+                        index,
+                        source: dest,
+                    },
+                    catch.name_pos.clone(),
+                );
+            }
+        }
+        generate(context, &catch.body, dest)?;
+        let catch_end = context.code.len();
+        ends.push(context.emit(Instruction::Jump { target: 0 }, pos.clone()));
+        if finally.is_some() {
+            handlers.push((catch_start, catch_end, context.code.len(), error));
+        }
+    } else {
+        handlers.push((start, body_end, context.code.len(), error));
+    }
+    context.finally -= guarded;
+    if let Some(finally) = finally {
+        context.emit(
+            Instruction::Load {
+                dest: flag,
+                value: Value::Boolean(true),
+            },
+            pos.clone(),
+        );
+        let rethrow = context.emit(Instruction::Jump { target: 0 }, pos.clone());
+        for address in ends.drain(..) {
+            context.patch(address);
+        }
+        context.emit(
+            Instruction::Load {
+                dest: flag,
+                value: Value::Boolean(false),
+            },
+            pos.clone(),
+        );
+        context.patch(rethrow);
+        let register = context.temp();
+        generate(context, finally, register)?;
+        let skip = context.emit(
+            Instruction::JumpIfFalse {
+                condition: flag,
+                target: 0,
+            },
+            pos.clone(),
+        );
+        context.emit(Instruction::Throw { source: error }, pos);
+        context.patch(skip);
+    }
+    for address in ends {
+        context.patch(address);
+    }
+    for (start, end, target, register) in handlers {
+        for range in handler::ranges(start, end, &context.bodies) {
+            context.handlers.push(Handler {
+                start: range.start,
+                end: range.end,
+                target,
+                register,
+            });
+        }
+    }
+    context.top = top;
+    Ok(())
+}
+
+fn field(context: &mut Context, name: &str, dest: Register, pos: Pos) -> CompileResult {
+    if name.len() > u16::MAX as usize {
+        return Err(CompileError {
+            message: "Field name is too long.".into(),
+            pos,
+        });
+    }
+    context.emit(
+        Instruction::Field {
+            dest,
+            source: dest,
+            name: name.into(),
+        },
+        pos,
+    );
+    Ok(())
+}
+
+fn postfix(
+    context: &mut Context,
+    node: &Node,
+    dest: Register,
+    skips: &mut Vec<usize>,
+) -> CompileResult {
+    match &node.kind {
+        Kind::Field {
+            object,
+            name,
+            access_pos,
+            safe,
+        } => {
+            postfix(context, object, dest, skips)?;
+            if *safe {
+                let skip = Instruction::JumpIfNil {
+                    source: dest,
+                    target: 0,
+                };
+                skips.push(context.emit(skip, access_pos.clone()));
+            }
+            field(context, name, dest, access_pos.clone())
+        }
+        Kind::Method {
+            receiver,
+            name,
+            arguments,
+            access_pos,
+            safe,
+        } => {
+            let top = context.top;
+            let first = context.temp();
+            postfix(context, receiver, first, skips)?;
+            if *safe {
+                let skip = Instruction::JumpIfNil {
+                    source: first,
+                    target: 0,
+                };
+                skips.push(context.emit(skip, access_pos.clone()));
+            }
+            let pos = access_pos.start..node.pos.end;
+            let name_pos = access_pos.clone();
+            call_native(context, name, name_pos, pos, arguments, dest, 1)?;
+            context.top = top;
+            Ok(())
+        }
+        Kind::Unwrap {
+            operand,
+            operator_pos,
+        } => {
+            postfix(context, operand, dest, skips)?;
+            let unwrap = Instruction::Unwrap { dest, source: dest };
+            early_return(context, unwrap, operator_pos.clone())
+        }
+        _ => generate(context, node, dest),
+    }
+}
+
+fn comparison(
+    context: &mut Context,
+    first: &Node,
+    rest: &[(ComparisonOperator, Pos, Node)],
+    dest: Register,
+) -> CompileResult {
+    let top = context.top;
+    let nodes: Vec<&Node> = rest.iter().map(|(_, _, node)| node).collect();
+    let mut left = operand(context, first, &nodes)?;
+    let mut ends = Vec::new();
+    for (index, (operator, pos, node)) in rest.iter().enumerate() {
+        let right = operand(context, node, &nodes[index + 1..])?;
+        let opcode = comparison_opcode(*operator);
+        context.emit(
+            Instruction::Binary {
+                opcode,
+                dest,
+                left,
+                right,
+            },
+            pos.clone(),
+        );
+        if index + 1 < rest.len() {
+            let skip = Instruction::JumpIfFalse {
+                condition: dest,
+                target: 0,
+            };
+            ends.push(context.emit(skip, pos.clone()));
+        }
+        left = right;
+    }
+    for address in ends {
+        context.patch(address);
+    }
+    context.top = top;
+    Ok(())
+}
+
+fn coalesce(
+    context: &mut Context,
+    node: &Node,
+    dest: Register,
+    ends: &mut Vec<usize>,
+) -> CompileResult {
+    match &node.kind {
+        Kind::Coalesce {
+            operator_pos,
+            left,
+            right,
+        } => {
+            coalesce(context, left, dest, ends)?;
+            let end = Instruction::JumpIfNotNil {
+                source: dest,
+                target: 0,
+            };
+            ends.push(context.emit(end, operator_pos.clone()));
+            generate(context, right, dest)
+        }
+        _ => generate(context, node, dest),
+    }
+}
+
+fn load(context: &mut Context, value: Value, dest: Register, pos: Pos) {
+    context.emit(Instruction::Load { dest, value }, pos);
+}
+
+fn generate(context: &mut Context, node: &Node, dest: Register) -> CompileResult {
+    let pos = node.pos.clone();
+    match &node.kind {
+        Kind::Void => load(context, Value::Void, dest, pos),
+        Kind::Yield => {
+            context.emit(Instruction::Yield { dest }, pos);
+        }
+        Kind::Nil => load(context, Value::Nil, dest, pos),
+        Kind::Boolean(value) => load(context, Value::Boolean(*value), dest, pos),
+        Kind::Integer(value) => load(context, Value::Integer(*value), dest, pos),
+        Kind::Real(value) => load(context, Value::Real(*value), dest, pos),
+        Kind::BigInteger(value) => {
+            if value.parts().1.len() > u16::MAX as usize {
+                return Err(CompileError {
+                    message: "Integer literal is too big.".into(),
+                    pos,
+                });
+            }
+            load(context, Value::from_big(value.clone()), dest, pos)
+        }
+        Kind::Variable(name) => return variable(context, name, dest, pos),
+        Kind::Assign {
+            name,
+            name_pos,
+            value,
+        } => {
+            generate(context, value, dest)?;
+            match define(context, name, name_pos.clone())? {
+                Variable::Local(register) => context.emit(
+                    Instruction::Move {
+                        dest: register,
+                        source: dest,
+                    },
+                    name_pos.clone(),
+                ),
+                Variable::Global(index) => context.emit(
+                    Instruction::StoreGlobal {
+                        index,
+                        source: dest,
+                    },
+                    name_pos.clone(),
+                ),
+            };
+        }
+        Kind::Call { name, arguments } => {
+            return match context.functions.iter().rposition(|f| &f.name == name) {
+                Some(index) => call_function(context, index, arguments, dest, pos),
+                None => {
+                    let top = context.top;
+                    let name_pos = pos.start..pos.start + name.len();
+                    call_native(context, name, name_pos, pos, arguments, dest, 0)?;
+                    context.top = top;
+                    Ok(())
+                }
+            }
+        }
+        Kind::Field { .. } | Kind::Method { .. } | Kind::Unwrap { .. } => {
+            let mut skips = Vec::new();
+            postfix(context, node, dest, &mut skips)?;
+            if !skips.is_empty() {
+                let end = context.emit(Instruction::Jump { target: 0 }, pos.clone());
+                for address in skips {
+                    context.patch(address);
+                }
+                load(context, Value::Nil, dest, pos);
+                context.patch(end);
+            }
+        }
+        Kind::Negate {
+            operand: value,
+            operator_pos,
+        } => {
+            let top = context.top;
+            let source = operand(context, value, &[])?;
+            context.emit(Instruction::Negate { dest, source }, operator_pos.clone());
+            context.top = top;
+        }
+        Kind::Binary {
+            operator,
+            operator_pos,
+            left,
+            right,
+        } => {
+            let top = context.top;
+            let left = operand(context, left, &[right])?;
+            let right = operand(context, right, &[])?;
+            let opcode = binary_opcode(*operator);
+            context.emit(
+                Instruction::Binary {
+                    opcode,
+                    dest,
+                    left,
+                    right,
+                },
+                operator_pos.clone(),
+            );
+            context.top = top;
+        }
+        Kind::Comparison { first, rest } => return comparison(context, first, rest, dest),
+        Kind::Coalesce { .. } => {
+            let mut ends = Vec::new();
+            coalesce(context, node, dest, &mut ends)?;
+            for address in ends {
+                context.patch(address);
+            }
+        }
+        Kind::Conditional {
+            condition,
+            question_pos,
+            then,
+            colon_pos,
+            otherwise,
+        } => {
+            let top = context.top;
+            let condition = operand(context, condition, &[])?;
+            context.top = top;
+            let skip = Instruction::JumpIfFalse {
+                condition,
+                target: 0,
+            };
+            let skip = context.emit(skip, question_pos.clone());
+            generate(context, then, dest)?;
+            let end = context.emit(Instruction::Jump { target: 0 }, colon_pos.clone());
+            context.patch(skip);
+            generate(context, otherwise, dest)?;
+            context.patch(end);
+        }
+        Kind::Sequence(nodes) => {
+            for node in nodes {
+                generate(context, node, dest)?;
+            }
+        }
+        Kind::Result { ok, value } => {
+            let top = context.top;
+            let source = operand(context, value, &[])?;
+            context.emit(
+                Instruction::Wrap {
+                    dest,
+                    source,
+                    ok: *ok,
+                },
+                pos,
+            );
+            context.top = top;
+        }
+        Kind::Throw(value) => {
+            let top = context.top;
+            let source = operand(context, value, &[])?;
+            context.emit(Instruction::Throw { source }, pos);
+            context.top = top;
+        }
+        Kind::Return(value) => {
+            let top = context.top;
+            let source = operand(context, value, &[])?;
+            context.top = top;
+            return early_return(context, Instruction::Return { source }, pos);
+        }
+        Kind::Try {
+            keyword_pos,
+            body,
+            catch,
+            finally,
+        } => {
+            return try_catch(
+                context,
+                body,
+                catch.as_ref(),
+                finally.as_deref(),
+                dest,
+                keyword_pos.clone(),
+            )
+        }
+        Kind::Function {
+            name,
+            name_pos,
+            parameters,
+            body,
+        } => return function(context, name, name_pos.clone(), parameters, body, dest, pos),
+    }
+    Ok(())
+}
+
+pub fn generate_program(
+    node: Option<&Node>,
+    natives: &dyn Natives,
+    globals: &mut dyn Globals,
+) -> Result<Program, CompileError> {
+    let mut context = Context {
+        natives,
+        globals,
+        code: Vec::new(),
+        lines: Vec::new(),
+        handlers: Vec::new(),
+        functions: Vec::new(),
+        bodies: Vec::new(),
+        finally: 0,
+        locals: None,
+        top: 0,
+        registers: 0,
+    };
+    let result = context.temp();
+    if let Some(node) = node {
+        generate(&mut context, node, result)?;
+    }
+    context.emit(Instruction::End { source: result }, 0..0);
+    if context.registers > u16::MAX as usize {
+        return Err(CompileError {
+            message: "Program uses too many registers.".into(),
+            pos: 0..0,
+        });
+    }
+    Ok(Program {
+        code: context.code,
+        lines: context.lines,
+        handlers: context.handlers,
+        functions: context.functions,
+        registers: context.registers as u16,
+        warnings: Vec::new(),
+    })
+}
+
+pub fn compile<S: Stream>(
+    stream: &mut S,
+    natives: &dyn Natives,
+    globals: &mut dyn Globals,
+) -> Result<Program, CompileError> {
+    let mut warnings = Vec::new();
+    let node = parser::parse(stream)?.map(|node| fold::fold(node, &mut warnings));
+    let program = generate_program(node.as_ref(), natives, globals)?;
+    Ok(Program {
+        warnings,
+        ..program
+    })
+}
@@ -0,0 +1,130 @@
+use crate::{
+    compiler::Globals,
+    convert::IntoNative,
+    engine::{builtins, Error, Names, RuntimeError},
+    impls::{data_stack, slice_reader, static_data, token_stream},
+    native::Native,
+    state::{Arithmetic, Stack, State, VMResult},
+    value::Value,
+    vm::Status,
+};
+
+use super::{
+    compiler,
+    vm::{self, Registers},
+    Program,
+};
+
+pub struct Machine<S> {
+    state: State<S>,
+    names: Names,
+    registers: Registers,
+}
+
+impl<S: Stack> Machine<S> {
+    pub fn with_stack(stack: S) -> Self {
+        let mut state = State::new(stack);
+        builtins(&mut state);
+        Self {
+            state,
+            names: Names(Vec::new()),
+            registers: Registers::default(),
+        }
+    }
+
+    pub fn register<F>(&mut self, name: &str, arity: u8, function: F) -> Native
+    where
+        F: Fn(&mut State<S>, &[Value]) -> VMResult<Value> + 'static,
+    {
+        self.state.natives.register(name, arity, function)
+    }
+
+    pub fn register_fn<Args, F>(&mut self, name: &str, function: F) -> Native
+    where
+        F: IntoNative<S, Args> + 'static,
+    {
+        self.state.natives.register_fn(name, function)
+    }
+
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.state.arithmetic = arithmetic;
+    }
+
+    pub fn global(&self, name: &str) -> Option<Value> {
+        let index = self.names.find(name)?;
+        self.state.globals.get(index as usize).cloned()
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        if let Some(index) = self.names.define(name) {
+            let index = index as usize;
+            if index >= self.state.globals.len() {
+                self.state.globals.resize(index + 1, Value::Void);
+            }
+            self.state.globals[index] = value;
+        }
+    }
+
+    pub fn compile(&mut self, source: &str) -> Result<Program, Error> {
+        let mut stream = token_stream::new(slice_reader::new(source.as_bytes()));
+        let count = self.names.0.len();
+        compiler::compile(&mut stream, &self.state.natives, &mut self.names).map_err(|error| {
+            self.names.0.truncate(count);
+            error.into()
+        })
+    }
+
+    pub fn start(&mut self, program: &Program) {
+        self.state.reset();
+        self.registers.reset(program);
+    }
+
+    pub fn resume(&mut self, program: &Program) -> Result<Status, Error> {
+        self.registers.yielded = None;
+        loop {
+            let error = match vm::run(&mut self.state, program, &mut self.registers) {
+                Ok(status) => return Ok(status),
+                Err(error) => error,
+            };
+            if let Err(error) = vm::unwind(&mut self.state, program, &mut self.registers, error) {
+                return Err(Error::Runtime(RuntimeError {
+                    error,
+                    message: self.state.message.take(),
+                    pos: program.lines.get(self.state.program_counter).cloned(),
+                    backtrace: self
+                        .registers
+                        .frames
+                        .iter()
+                        .rev()
+                        .filter_map(|frame| program.lines.get(frame.return_address - 1).cloned())
+                        .collect(),
+                }));
+            }
+        }
+    }
+
+    pub fn resume_with(&mut self, program: &Program, value: Value) -> Result<Status, Error> {
+        if let Some(index) = self.registers.yielded.take() {
+            self.registers.values[index] = value;
+        }
+        self.resume(program)
+    }
+
+    pub fn run(&mut self, program: &Program) -> Result<Value, Error> {
+        self.start(program);
+        loop {
+            if let Status::Finished(value) = self.resume(program)? {
+                return Ok(value);
+            }
+        }
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let program = self.compile(source)?;
+        self.run(&program)
+    }
+}
+
+pub fn new() -> Machine<impl Stack> {
+    Machine::with_stack(data_stack::new(static_data::new::<256>()))
+}
@@ -0,0 +1,115 @@
+pub mod compiler;
+pub mod engine;
+pub mod vm;
+
+use crate::{compiler::Warning, token::Pos, value::Value};
+
+pub type Register = u16;
+
+#[derive(Clone, Debug)]
+pub enum Instruction {
+    Load {
+        dest: Register,
+        value: Value,
+    },
+    Move {
+        dest: Register,
+        source: Register,
+    },
+    LoadGlobal {
+        dest: Register,
+        index: u16,
+    },
+    StoreGlobal {
+        index: u16,
+        source: Register,
+    },
+    Binary {
+        opcode: u8,
+        dest: Register,
+        left: Register,
+        right: Register,
+    },
+    Negate {
+        dest: Register,
+        source: Register,
+    },
+    Field {
+        dest: Register,
+        source: Register,
+        name: Box<str>,
+    },
+    Wrap {
+        dest: Register,
+        source: Register,
+        ok: bool,
+    },
+    Unwrap {
+        dest: Register,
+        source: Register,
+    },
+    Jump {
+        target: usize,
+    },
+    JumpIfFalse {
+        condition: Register,
+        target: usize,
+    },
+    JumpIfNil {
+        source: Register,
+        target: usize,
+    },
+    JumpIfNotNil {
+        source: Register,
+        target: usize,
+    },
+    CallNative {
+        dest: Register,
+        index: u16,
+        first: Register,
+        count: u8,
+    },
+    Call {
+        dest: Register,
+        function: usize,
+        first: Register,
+        count: u8,
+    },
+    Return {
+        source: Register,
+    },
+    Throw {
+        source: Register,
+    },
+    Yield {
+        dest: Register,
+    },
+    End {
+        source: Register,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Handler {
+    pub start: usize,
+    pub end: usize,
+    pub target: usize,
+    pub register: Register,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: Box<str>,
+    pub address: usize,
+    pub arity: u8,
+    pub registers: u16,
+}
+
+pub struct Program {
+    pub code: Vec<Instruction>,
+    pub lines: Vec<Pos>,
+    pub handlers: Vec<Handler>,
+    pub functions: Vec<Function>,
+    pub registers: u16,
+    pub warnings: Vec<Warning>,
+}
@@ -0,0 +1,232 @@
+use crate::{
+    state::{Stack, State, VMError, VMResult},
+    value::Value,
+    vm::Status,
+};
+
+use super::{Instruction, Program};
+
+const FRAMES: usize = 256;
+
+pub struct Frame {
+    pub return_address: usize,
+    pub base: usize,
+    pub dest: usize,
+}
+
+#[derive(Default)]
+pub struct Registers {
+    pub values: Vec<Value>,
+    pub frames: Vec<Frame>,
+    pub yielded: Option<usize>,
+}
+
+impl Registers {
+    pub fn reset(&mut self, program: &Program) {
+        self.values.clear();
+        self.values.resize(program.registers as usize, Value::Void);
+        self.frames.clear();
+        self.yielded = None;
+    }
+
+    fn base(&self) -> usize {
+        self.frames.last().map_or(0, |frame| frame.base)
+    }
+
+    fn ret(&mut self, value: Value) -> VMResult<usize> {
+        let frame = self.frames.pop().ok_or(VMError::Frame)?;
+        self.values.truncate(frame.base);
+        self.values[frame.dest] = value;
+        Ok(frame.return_address)
+    }
+}
+
+pub fn run<S: Stack>(
+    state: &mut State<S>,
+    program: &Program,
+    registers: &mut Registers,
+) -> VMResult<Status> {
+    let mut base = registers.base();
+    loop {
+        let instruction = program
+            .code
+            .get(state.program_counter)
+            .ok_or(VMError::OpcodeFetch)?;
+        let values = &mut registers.values;
+        match instruction {
+            Instruction::Load { dest, value } => {
+                values[base + *dest as usize] = value.clone();
+            }
+            Instruction::Move { dest, source } => {
+                values[base + *dest as usize] = values[base + *source as usize].clone();
+            }
+            Instruction::LoadGlobal { dest, index } => {
+                let value = state
+                    .globals
+                    .get(*index as usize)
+                    .cloned()
+                    .unwrap_or(Value::Void);
+                values[base + *dest as usize] = value;
+            }
+            Instruction::StoreGlobal { index, source } => {
+                let index = *index as usize;
+                if index >= state.globals.len() {
+                    state.globals.resize(index + 1, Value::Void);
+                }
+                state.globals[index] = values[base + *source as usize].clone();
+            }
+            Instruction::Binary {
+                opcode,
+                dest,
+                left,
+                right,
+            } => {
+                let left = values[base + *left as usize].clone();
+                let right = values[base + *right as usize].clone();
+                values[base + *dest as usize] = state.apply(*opcode, left, right)?;
+            }
+            Instruction::Negate { dest, source } => {
+                let value = values[base + *source as usize].clone();
+                values[base + *dest as usize] = state.apply_negate(value)?;
+            }
+            Instruction::Field { dest, source, name } => {
+                let value = values[base + *source as usize].clone();
+                values[base + *dest as usize] = state.field_of(value, name)?;
+            }
+            Instruction::Wrap { dest, source, ok } => {
+                let value = values[base + *source as usize].clone().into();
+                values[base + *dest as usize] = match ok {
+                    true => Value::Ok(value),
+                    false => Value::Err(value),
+                };
+            }
+            Instruction::Unwrap { dest, source } => match values[base + *source as usize].clone() {
+                Value::Ok(value) => values[base + *dest as usize] = (*value).clone(),
+                Value::Err(value) => {
+                    state.program_counter = registers.ret(Value::Err(value))?;
+                    base = registers.base();
+                    continue;
+                }
+                value => return state.result_error(value),
+            },
+            Instruction::Jump { target } => {
+                state.program_counter = *target;
+                continue;
+            }
+            Instruction::JumpIfFalse { condition, target } => {
+                if !state.condition(&values[base + *condition as usize])? {
+                    state.program_counter = *target;
+                    continue;
+                }
+            }
+            Instruction::JumpIfNil { source, target } => {
+                if values[base + *source as usize] == Value::Nil {
+                    state.program_counter = *target;
+                    continue;
+                }
+            }
+            Instruction::JumpIfNotNil { source, target } => {
+                if values[base + *source as usize] != Value::Nil {
+                    state.program_counter = *target;
+                    continue;
+                }
+            }
+            Instruction::CallNative {
+                dest,
+                index,
+                first,
+                count,
+            } => {
+                let start = base + *first as usize;
+                let arguments = values[start..start + *count as usize].to_vec();
+                let value = state.call_native_with(*index, &arguments)?;
+                registers.values[base + *dest as usize] = value;
+            }
+            Instruction::Call {
+                dest,
+                function,
+                first,
+                count,
+            } => {
+                let function = program.functions.get(*function).ok_or(VMError::Frame)?;
+                if registers.frames.len() >= FRAMES {
+                    return Err(VMError::StackOverflow);
+                }
+                let start = base + *first as usize;
+                let frame = Frame {
+                    return_address: state.program_counter + 1,
+                    base: values.len(),
+                    dest: base + *dest as usize,
+                };
+                values.extend_from_within(start..start + *count as usize);
+                values.resize(frame.base + function.registers as usize, Value::Void);
+                base = frame.base;
+                registers.frames.push(frame);
+                state.program_counter = function.address;
+                continue;
+            }
+            Instruction::Return { source } => {
+                let value = values[base + *source as usize].clone();
+                state.program_counter = registers.ret(value)?;
+                base = registers.base();
+                continue;
+            }
+            Instruction::Throw { source } => {
+                let value = values[base + *source as usize].clone();
+                return state.throw_value(value);
+            }
+            Instruction::Yield { dest } => {
+                values[base + *dest as usize] = Value::Void;
+                registers.yielded = Some(base + *dest as usize);
+                state.program_counter += 1;
+                return Ok(Status::Yielded);
+            }
+            Instruction::End { source } => {
+                let value = core::mem::replace(&mut values[base + *source as usize], Value::Void);
+                return Ok(Status::Finished(value));
+            }
+        }
+        state.program_counter += 1;
+    }
+}
+
+pub fn unwind<S: Stack>(
+    state: &mut State<S>,
+    program: &Program,
+    registers: &mut Registers,
+    error: VMError,
+) -> VMResult<()> {
+    let mut address = state.program_counter;
+    let mut frames = registers.frames.len();
+    let handler = loop {
+        let handler = program
+            .handlers
+            .iter()
+            .find(|handler| (handler.start..handler.end).contains(&address));
+        if let Some(handler) = handler {
+            break handler;
+        }
+        match frames.checked_sub(1) {
+            Some(frame) => {
+                address = registers.frames[frame].return_address - 1;
+                frames = frame;
+            }
+            None => return Err(error),
+        }
+    };
+    let value = match state.exception.take() {
+        Some(value) => value,
+        None => match state.message.take() {
+            Some(message) => Value::String(message.into()),
+            None => Value::String(error.to_string().into()),
+        },
+    };
+    if let Some(frame) = registers.frames.get(frames) {
+        registers.values.truncate(frame.base);
+    }
+    registers.frames.truncate(frames);
+    let base = registers.base();
+    registers.values[base + handler.register as usize] = value;
+    state.program_counter = handler.target;
+    Ok(())
+}
@@ -1,6 +1,8 @@
 use core::fmt;
 
-use crate::value::Value;
+use core::cmp::Ordering;
+
+use crate::{bigint::BigInt, native::Registry, opcode::*, value::Value, vm::Status};
 
 pub enum VMError {
     StackOverflow,
@@ -8,7 +10,16 @@ pub enum VMError {
     UnknownInstruction,
     OpcodeFetch,
     BinaryOperator,
+    UnaryOperator,
     DividingByZero,
+    UnknownNative,
+    NativeCall,
+    IntegerOverflow,
+    Condition,
+    Field,
+    Exception,
+    Frame,
+    Constant,
 }
 
 impl fmt::Display for VMError {
@@ -19,7 +30,16 @@ impl fmt::Display for VMError {
             VMError::UnknownInstruction => write!(f, "Unknown instruction."),
             VMError::OpcodeFetch => write!(f, "Unable to fetch opcode."),
             VMError::BinaryOperator => write!(f, "Binary operator error."),
+            VMError::UnaryOperator => write!(f, "Unary operator error."),
             VMError::DividingByZero => write!(f, "Dividing by zero."),
+            VMError::UnknownNative => write!(f, "Unknown native function."),
+            VMError::NativeCall => write!(f, "Native function call error."),
+            VMError::IntegerOverflow => write!(f, "Integer overflow."),
+            VMError::Condition => write!(f, "Condition is not a boolean."),
+            VMError::Field => write!(f, "Field access error."),
+            VMError::Exception => write!(f, "Uncaught exception."),
+            VMError::Frame => write!(f, "Call frame error."),
+            VMError::Constant => write!(f, "Unknown constant."),
         }
     }
 }
@@ -29,12 +49,69 @@ pub type VMResult<T> = Result<T, VMError>;
 pub trait Stack {
     fn push(&mut self, value: Value) -> VMResult<()>;
     fn pop(&mut self) -> VMResult<Value>;
+    fn clear(&mut self);
+    fn len(&self) -> usize;
+    fn get(&self, index: usize) -> VMResult<Value>;
+    fn set(&mut self, index: usize, value: Value) -> VMResult<()>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Arithmetic {
+    #[default]
+    Wrapping,
+    Checked,
+    Saturating,
+    Promoting,
+}
+
+type IntegerOp = fn(i64, i64) -> Option<i64>;
+type BigOp = fn(&BigInt, &BigInt) -> Option<BigInt>;
+type RetryOp<S> = fn(&mut State<S>, Value, Value) -> VMResult<Value>;
+
+fn wrapping_power(mut base: i64, mut exponent: i64) -> i64 {
+    let mut result = 1i64;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    result
+}
+
+fn floor_divide(l: i64, r: i64, divide: IntegerOp) -> Option<i64> {
+    let quotient = divide(l, r)?;
+    if l.wrapping_rem(r) != 0 && (l < 0) != (r < 0) {
+        Some(quotient - 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+const FRAMES: usize = 256;
+
+pub struct Frame {
+    pub return_address: usize,
+    pub base: usize,
+    pub start: usize,
 }
 
 pub struct State<S> {
     stack: S,
     pub program_counter: usize,
     pub message: Option<Box<str>>,
+    pub breakpoints: Vec<usize>,
+    pub natives: Registry<S>,
+    pub globals: Vec<Value>,
+    pub constants: Vec<f64>,
+    pub arithmetic: Arithmetic,
+    pub exception: Option<Value>,
+    pub frames: Vec<Frame>,
 }
 
 impl<S: Stack> State<S> {
@@ -43,9 +120,24 @@ impl<S: Stack> State<S> {
             stack,
             program_counter: 0,
             message: None,
+            breakpoints: Vec::new(),
+            natives: Registry::new(),
+            globals: Vec::new(),
+            constants: Vec::new(),
+            arithmetic: Arithmetic::default(),
+            exception: None,
+            frames: Vec::new(),
         }
     }
 
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.program_counter = 0;
+        self.message = None;
+        self.exception = None;
+        self.frames.clear();
+    }
+
     pub fn push(&mut self, value: Value) -> VMResult<()> {
         self.stack.push(value)
     }
@@ -54,6 +146,99 @@ impl<S: Stack> State<S> {
         self.stack.pop()
     }
 
+    pub fn call(&mut self, address: usize, count: u8, return_address: usize) -> VMResult<()> {
+        if self.frames.len() >= FRAMES {
+            return Err(VMError::StackOverflow);
+        }
+        let depth = self.stack.len();
+        let base = depth
+            .checked_sub(count as usize)
+            .ok_or(VMError::StackUnderflow)?;
+        self.frames.push(Frame {
+            return_address,
+            base,
+            start: depth,
+        });
+        self.program_counter = address;
+        Ok(())
+    }
+
+    pub fn reserve(&mut self, count: u8) -> VMResult<()> {
+        for _ in 0..count {
+            self.push(Value::Void)?;
+        }
+        let depth = self.stack.len();
+        match self.frames.last_mut() {
+            Some(frame) => {
+                frame.start = depth;
+                Ok(())
+            }
+            None => Err(VMError::Frame),
+        }
+    }
+
+    pub fn ret(&mut self) -> VMResult<()> {
+        let value = self.pop()?;
+        let frame = self.frames.pop().ok_or(VMError::Frame)?;
+        self.truncate(frame.base)?;
+        self.push(value)?;
+        self.program_counter = frame.return_address;
+        Ok(())
+    }
+
+    pub fn load_constant(&mut self, index: u16) -> VMResult<()> {
+        let value = *self
+            .constants
+            .get(index as usize)
+            .ok_or(VMError::Constant)?;
+        self.push(Value::Real(value))
+    }
+
+    pub fn load_local(&mut self, index: u8) -> VMResult<()> {
+        let base = self.frames.last().ok_or(VMError::Frame)?.base;
+        let value = self.stack.get(base + index as usize)?;
+        self.push(value)
+    }
+
+    pub fn store_local(&mut self, index: u8) -> VMResult<()> {
+        let base = self.frames.last().ok_or(VMError::Frame)?.base;
+        let value = self.pop()?;
+        self.stack.set(base + index as usize, value.clone())?;
+        self.push(value)
+    }
+
+    pub fn unwrap_result(&mut self) -> VMResult<Option<Status>> {
+        match self.pop()? {
+            Value::Ok(value) => {
+                self.push(value.as_ref().clone())?;
+                self.program_counter += 1;
+            }
+            value @ Value::Err(_) => {
+                self.push(value)?;
+                self.ret()?;
+            }
+            value => return self.result_error(value),
+        }
+        Ok(None)
+    }
+
+    pub fn result_error<T>(&mut self, value: Value) -> VMResult<T> {
+        self.error(
+            format!(
+                "Unable to use '?' for {} '{value}', expected result.",
+                value.kind()
+            ),
+            VMError::UnaryOperator,
+        )
+    }
+
+    pub fn truncate(&mut self, depth: usize) -> VMResult<()> {
+        while self.stack.len() > depth {
+            self.stack.pop()?;
+        }
+        Ok(())
+    }
+
     fn binary<F>(&mut self, f: F) -> VMResult<()>
     where
         F: Fn(&mut Self, Value, Value) -> VMResult<Value>,
@@ -64,54 +249,176 @@ impl<S: Stack> State<S> {
         self.push(result)
     }
 
-    fn error<T>(&mut self, m: String, e: VMError) -> VMResult<T> {
+    fn unary<F>(&mut self, f: F) -> VMResult<()>
+    where
+        F: Fn(&mut Self, Value) -> VMResult<Value>,
+    {
+        let value = self.pop()?;
+        let result = f(self, value)?;
+        self.push(result)
+    }
+
+    pub fn error<T>(&mut self, m: String, e: VMError) -> VMResult<T> {
         self.message = Some(m.into_boxed_str());
         Err(e)
     }
 
-    pub fn single<F>(&mut self, f: F) -> VMResult<bool>
+    pub fn single<F>(&mut self, f: F) -> VMResult<Option<Status>>
     where
         F: Fn(&mut Self) -> VMResult<()>,
     {
         f(self)?;
         self.program_counter += 1;
-        Ok(true)
+        Ok(None)
     }
 
     fn op_error(&mut self, operator: &str, l: Value, r: Value) -> VMResult<Value> {
+        if l == Value::Nil || r == Value::Nil {
+            return self.error(
+                format!("Unable to use '{operator}' for {l} and {r} values, operand is nil."),
+                VMError::BinaryOperator,
+            );
+        }
         self.error(
             format!("Unable to use '{operator}' for {l} and {r} values."),
             VMError::BinaryOperator,
         )
     }
 
+    fn integer_op(
+        &mut self,
+        operator: &str,
+        l: i64,
+        r: i64,
+        [wrapping, checked, saturating]: [IntegerOp; 3],
+        big: BigOp,
+    ) -> VMResult<Value> {
+        let result = match self.arithmetic {
+            Arithmetic::Wrapping => wrapping(l, r),
+            Arithmetic::Checked => checked(l, r),
+            Arithmetic::Saturating => saturating(l, r),
+            Arithmetic::Promoting => match checked(l, r) {
+                Some(value) => Some(value),
+                None => {
+                    return match big(&BigInt::from_i64(l), &BigInt::from_i64(r)) {
+                        Some(value) => Ok(Value::from_big(value)),
+                        None => self.division_error(operator, Value::Integer(l), Value::Integer(r)),
+                    }
+                }
+            },
+        };
+        match result {
+            Some(value) => Ok(Value::Integer(value)),
+            None => self.overflow_error(operator, Value::Integer(l), Value::Integer(r)),
+        }
+    }
+
+    fn overflow_error(&mut self, operator: &str, l: Value, r: Value) -> VMResult<Value> {
+        self.error(
+            format!("Integer overflow in '{operator}' for {l} and {r}."),
+            VMError::IntegerOverflow,
+        )
+    }
+
+    fn division_error(&mut self, operator: &str, l: Value, r: Value) -> VMResult<Value> {
+        self.error(
+            format!("Unable to use '{operator}' for {l} and {r}, dividing by zero."),
+            VMError::DividingByZero,
+        )
+    }
+
+    fn big_op(
+        &mut self,
+        operator: &str,
+        l: Value,
+        r: Value,
+        big: BigOp,
+        retry: RetryOp<S>,
+    ) -> VMResult<Value> {
+        match (l.to_big(), r.to_big()) {
+            (Some(bl), Some(br)) => match big(&bl, &br) {
+                Some(value) => Ok(Value::from_big(value)),
+                None => self.division_error(operator, l, r),
+            },
+            _ => match (l, r) {
+                (Value::BigInt(l), r @ Value::Real(_)) => retry(self, Value::Real(l.to_f64()), r),
+                (l @ Value::Real(_), Value::BigInt(r)) => retry(self, l, Value::Real(r.to_f64())),
+                (l, r) => self.op_error(operator, l, r),
+            },
+        }
+    }
+
+    fn compare_op(
+        &mut self,
+        operator: &str,
+        l: Value,
+        r: Value,
+        compare: fn(Ordering) -> bool,
+    ) -> VMResult<Value> {
+        match l.compare(&r) {
+            Some(ordering) => Ok(Value::Boolean(compare(ordering))),
+            None if l.is_number() && r.is_number() => Ok(Value::Boolean(false)),
+            None => self.op_error(operator, l, r),
+        }
+    }
+
     fn op_addict(&mut self, l: Value, r: Value) -> VMResult<Value> {
         match (l, r) {
-            (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l.wrapping_add(r))),
+            (Value::Integer(l), Value::Integer(r)) => self.integer_op(
+                "+",
+                l,
+                r,
+                [
+                    |l, r| Some(l.wrapping_add(r)),
+                    i64::checked_add,
+                    |l, r| Some(l.saturating_add(r)),
+                ],
+                |l, r| Some(l.add(r)),
+            ),
             (Value::Integer(l), Value::Real(r)) => Ok(Value::Real(l as f64 + r)),
             (Value::Real(l), Value::Integer(r)) => Ok(Value::Real(l + r as f64)),
             (Value::Real(l), Value::Real(r)) => Ok(Value::Real(l + r)),
-            _ => self.op_error("+", l, r),
+            (l, r) => self.big_op("+", l, r, |l, r| Some(l.add(r)), Self::op_addict),
         }
     }
 
     fn op_multiply(&mut self, l: Value, r: Value) -> VMResult<Value> {
         match (l, r) {
-            (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l.wrapping_mul(r))),
+            (Value::Integer(l), Value::Integer(r)) => self.integer_op(
+                "*",
+                l,
+                r,
+                [
+                    |l, r| Some(l.wrapping_mul(r)),
+                    i64::checked_mul,
+                    |l, r| Some(l.saturating_mul(r)),
+                ],
+                |l, r| Some(l.multiply(r)),
+            ),
             (Value::Integer(l), Value::Real(r)) => Ok(Value::Real(l as f64 * r)),
             (Value::Real(l), Value::Integer(r)) => Ok(Value::Real(l * r as f64)),
             (Value::Real(l), Value::Real(r)) => Ok(Value::Real(l * r)),
-            _ => self.op_error("*", l, r),
+            (l, r) => self.big_op("*", l, r, |l, r| Some(l.multiply(r)), Self::op_multiply),
         }
     }
 
     fn op_subtract(&mut self, l: Value, r: Value) -> VMResult<Value> {
         match (l, r) {
-            (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l.wrapping_sub(r))),
+            (Value::Integer(l), Value::Integer(r)) => self.integer_op(
+                "-",
+                l,
+                r,
+                [
+                    |l, r| Some(l.wrapping_sub(r)),
+                    i64::checked_sub,
+                    |l, r| Some(l.saturating_sub(r)),
+                ],
+                |l, r| Some(l.subtract(r)),
+            ),
             (Value::Integer(l), Value::Real(r)) => Ok(Value::Real(l as f64 - r)),
             (Value::Real(l), Value::Integer(r)) => Ok(Value::Real(l - r as f64)),
             (Value::Real(l), Value::Real(r)) => Ok(Value::Real(l - r)),
-            _ => self.op_error("-", l, r),
+            (l, r) => self.big_op("-", l, r, |l, r| Some(l.subtract(r)), Self::op_subtract),
         }
     }
 
@@ -119,15 +426,31 @@ impl<S: Stack> State<S> {
         match (l, r) {
             (Value::Integer(l), Value::Integer(r)) => {
                 if r == 0 {
-                    Err(VMError::DividingByZero)
+                    self.division_error("/", Value::Integer(l), Value::Integer(r))
                 } else {
-                    Ok(Value::Integer(l.wrapping_div(r)))
+                    self.integer_op(
+                        "/",
+                        l,
+                        r,
+                        [
+                            |l, r| Some(l.wrapping_div(r)),
+                            i64::checked_div,
+                            |l, r| Some(l.saturating_div(r)),
+                        ],
+                        |l, r| l.div_rem(r).map(|(q, _)| q),
+                    )
                 }
             }
             (Value::Integer(l), Value::Real(r)) => Ok(Value::Real(l as f64 / r)),
             (Value::Real(l), Value::Integer(r)) => Ok(Value::Real(l / r as f64)),
             (Value::Real(l), Value::Real(r)) => Ok(Value::Real(l / r)),
-            _ => self.op_error("/", l, r),
+            (l, r) => self.big_op(
+                "/",
+                l,
+                r,
+                |l, r| l.div_rem(r).map(|(q, _)| q),
+                Self::op_divide,
+            ),
         }
     }
 
@@ -135,129 +458,383 @@ impl<S: Stack> State<S> {
         match (l, r) {
             (Value::Integer(l), Value::Integer(r)) => {
                 if r == 0 {
-                    Err(VMError::DividingByZero)
+                    self.division_error("%", Value::Integer(l), Value::Integer(r))
                 } else {
-                    Ok(Value::Integer(l.wrapping_rem(r)))
+                    self.integer_op(
+                        "%",
+                        l,
+                        r,
+                        [
+                            |l, r| Some(l.wrapping_rem(r)),
+                            i64::checked_rem,
+                            |l, r| Some(l.wrapping_rem(r)),
+                        ],
+                        |l, r| l.div_rem(r).map(|(_, r)| r),
+                    )
                 }
             }
             (Value::Integer(l), Value::Real(r)) => Ok(Value::Real(l as f64 % r)),
             (Value::Real(l), Value::Integer(r)) => Ok(Value::Real(l % r as f64)),
             (Value::Real(l), Value::Real(r)) => Ok(Value::Real(l % r)),
-            _ => self.op_error("%", l, r),
+            (l, r) => self.big_op(
+                "%",
+                l,
+                r,
+                |l, r| l.div_rem(r).map(|(_, r)| r),
+                Self::op_module,
+            ),
         }
     }
 
-    fn op_less(&mut self, l: Value, r: Value) -> VMResult<Value> {
+    fn op_floor_divide(&mut self, l: Value, r: Value) -> VMResult<Value> {
         match (l, r) {
-            (Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l < r)),
-            (Value::Integer(l), Value::Real(r)) => Ok(Value::Boolean((l as f64) < r)),
-            (Value::Real(l), Value::Integer(r)) => Ok(Value::Boolean(l < r as f64)),
-            (Value::Real(l), Value::Real(r)) => Ok(Value::Boolean(l < r)),
-            _ => self.op_error("<", l, r),
+            (Value::Integer(l), Value::Integer(r)) => {
+                if r == 0 {
+                    self.division_error("//", Value::Integer(l), Value::Integer(r))
+                } else {
+                    self.integer_op(
+                        "//",
+                        l,
+                        r,
+                        [
+                            |l, r| floor_divide(l, r, |l, r| Some(l.wrapping_div(r))),
+                            |l, r| floor_divide(l, r, i64::checked_div),
+                            |l, r| floor_divide(l, r, |l, r| Some(l.saturating_div(r))),
+                        ],
+                        |l, r| l.div_rem_floor(r).map(|(q, _)| q),
+                    )
+                }
+            }
+            (Value::Integer(l), Value::Real(r)) => Ok(Value::Real((l as f64 / r).floor())),
+            (Value::Real(l), Value::Integer(r)) => Ok(Value::Real((l / r as f64).floor())),
+            (Value::Real(l), Value::Real(r)) => Ok(Value::Real((l / r).floor())),
+            (l, r) => self.big_op(
+                "//",
+                l,
+                r,
+                |l, r| l.div_rem_floor(r).map(|(q, _)| q),
+                Self::op_floor_divide,
+            ),
         }
     }
 
-    fn op_greater(&mut self, l: Value, r: Value) -> VMResult<Value> {
+    fn op_euclid_module(&mut self, l: Value, r: Value) -> VMResult<Value> {
         match (l, r) {
-            (Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l > r)),
-            (Value::Integer(l), Value::Real(r)) => Ok(Value::Boolean((l as f64) > r)),
-            (Value::Real(l), Value::Integer(r)) => Ok(Value::Boolean(l > r as f64)),
-            (Value::Real(l), Value::Real(r)) => Ok(Value::Boolean(l > r)),
-            _ => self.op_error(">", l, r),
+            (Value::Integer(l), Value::Integer(r)) => {
+                if r == 0 {
+                    self.division_error("%%", Value::Integer(l), Value::Integer(r))
+                } else {
+                    self.integer_op(
+                        "%%",
+                        l,
+                        r,
+                        [
+                            |l, r| Some(l.wrapping_rem_euclid(r)),
+                            i64::checked_rem_euclid,
+                            |l, r| Some(l.wrapping_rem_euclid(r)),
+                        ],
+                        BigInt::rem_euclid,
+                    )
+                }
+            }
+            (Value::Integer(l), Value::Real(r)) => Ok(Value::Real((l as f64).rem_euclid(r))),
+            (Value::Real(l), Value::Integer(r)) => Ok(Value::Real(l.rem_euclid(r as f64))),
+            (Value::Real(l), Value::Real(r)) => Ok(Value::Real(l.rem_euclid(r))),
+            (l, r) => self.big_op("%%", l, r, BigInt::rem_euclid, Self::op_euclid_module),
         }
     }
 
+    fn op_less(&mut self, l: Value, r: Value) -> VMResult<Value> {
+        self.compare_op("<", l, r, Ordering::is_lt)
+    }
+
+    fn op_greater(&mut self, l: Value, r: Value) -> VMResult<Value> {
+        self.compare_op(">", l, r, Ordering::is_gt)
+    }
+
     fn op_less_equals(&mut self, l: Value, r: Value) -> VMResult<Value> {
-        match (l, r) {
-            (Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l <= r)),
-            (Value::Integer(l), Value::Real(r)) => Ok(Value::Boolean((l as f64) <= r)),
-            (Value::Real(l), Value::Integer(r)) => Ok(Value::Boolean(l <= r as f64)),
-            (Value::Real(l), Value::Real(r)) => Ok(Value::Boolean(l <= r)),
-            _ => self.op_error("<=", l, r),
-        }
+        self.compare_op("<=", l, r, Ordering::is_le)
     }
 
     fn op_greater_equals(&mut self, l: Value, r: Value) -> VMResult<Value> {
-        match (l, r) {
-            (Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l >= r)),
-            (Value::Integer(l), Value::Real(r)) => Ok(Value::Boolean((l as f64) >= r)),
-            (Value::Real(l), Value::Integer(r)) => Ok(Value::Boolean(l >= r as f64)),
-            (Value::Real(l), Value::Real(r)) => Ok(Value::Boolean(l >= r)),
-            _ => self.op_error(">=", l, r),
-        }
+        self.compare_op(">=", l, r, Ordering::is_ge)
     }
 
     fn op_equals(&mut self, l: Value, r: Value) -> VMResult<Value> {
-        match (l, r) {
-            (Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l == r)),
-            (Value::Integer(l), Value::Real(r)) => Ok(Value::Boolean((l as f64) == r)),
-            (Value::Real(l), Value::Integer(r)) => Ok(Value::Boolean(l == r as f64)),
-            (Value::Real(l), Value::Real(r)) => Ok(Value::Boolean(l == r)),
-            _ => self.op_error("==", l, r),
-        }
+        Ok(Value::Boolean(l.equals(&r)))
     }
 
     fn op_not_equals(&mut self, l: Value, r: Value) -> VMResult<Value> {
-        match (l, r) {
-            (Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l != r)),
-            (Value::Integer(l), Value::Real(r)) => Ok(Value::Boolean((l as f64) != r)),
-            (Value::Real(l), Value::Integer(r)) => Ok(Value::Boolean(l != r as f64)),
-            (Value::Real(l), Value::Real(r)) => Ok(Value::Boolean(l != r)),
-            _ => self.op_error("!=", l, r),
-        }
+        Ok(Value::Boolean(!l.equals(&r)))
     }
 
     fn op_and(&mut self, l: Value, r: Value) -> VMResult<Value> {
         match (l, r) {
             (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l & r)),
-            _ => self.op_error("&", l, r),
+            (l, r) => self.op_error("&", l, r),
         }
     }
 
     fn op_or(&mut self, l: Value, r: Value) -> VMResult<Value> {
         match (l, r) {
             (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l | r)),
-            _ => self.op_error("|", l, r),
+            (l, r) => self.op_error("|", l, r),
         }
     }
 
     fn op_xor(&mut self, l: Value, r: Value) -> VMResult<Value> {
         match (l, r) {
             (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l ^ r)),
-            _ => self.op_error("^", l, r),
+            (l, r) => self.op_error("^", l, r),
+        }
+    }
+
+    fn op_power(&mut self, l: Value, r: Value) -> VMResult<Value> {
+        match (l, r) {
+            (Value::Integer(l), Value::Integer(r)) if r >= 0 => {
+                let checked = match l {
+                    0 | 1 if r != 0 => Some(l),
+                    -1 => Some(if r % 2 == 0 { 1 } else { -1 }),
+                    _ => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+                };
+                match (checked, self.arithmetic) {
+                    (Some(value), _) => Ok(Value::Integer(value)),
+                    (None, Arithmetic::Wrapping) => Ok(Value::Integer(wrapping_power(l, r))),
+                    (None, Arithmetic::Saturating) => Ok(Value::Integer(if l < 0 && r % 2 == 1 {
+                        i64::MIN
+                    } else {
+                        i64::MAX
+                    })),
+                    (None, Arithmetic::Promoting) if r <= u32::MAX as i64 => {
+                        Ok(Value::from_big(BigInt::from_i64(l).power(r as u32)))
+                    }
+                    (None, _) => self.overflow_error("**", Value::Integer(l), Value::Integer(r)),
+                }
+            }
+            (Value::Integer(0), Value::Integer(r)) => {
+                self.division_error("**", Value::Integer(0), Value::Integer(r))
+            }
+            (Value::Integer(l), Value::Integer(r)) => Ok(Value::Real((l as f64).powf(r as f64))),
+            (Value::Integer(l), Value::Real(r)) => Ok(Value::Real((l as f64).powf(r))),
+            (Value::Real(l), Value::Integer(r)) => Ok(Value::Real(l.powf(r as f64))),
+            (Value::Real(l), Value::Real(r)) => Ok(Value::Real(l.powf(r))),
+            (Value::BigInt(l), Value::Integer(r)) if r >= 0 => {
+                if r <= u32::MAX as i64 {
+                    Ok(Value::from_big(l.power(r as u32)))
+                } else {
+                    self.overflow_error("**", Value::BigInt(l), Value::Integer(r))
+                }
+            }
+            (Value::BigInt(l), r @ (Value::Integer(_) | Value::Real(_))) => {
+                self.op_power(Value::Real(l.to_f64()), r)
+            }
+            (l @ (Value::Integer(_) | Value::Real(_)), Value::BigInt(r)) => {
+                self.op_power(l, Value::Real(r.to_f64()))
+            }
+            (l, r) => self.op_error("**", l, r),
+        }
+    }
+
+    fn op_negate(&mut self, value: Value) -> VMResult<Value> {
+        match value {
+            Value::Integer(value) => {
+                let result = match self.arithmetic {
+                    Arithmetic::Wrapping => Some(value.wrapping_neg()),
+                    Arithmetic::Saturating => Some(value.saturating_neg()),
+                    Arithmetic::Checked | Arithmetic::Promoting => value.checked_neg(),
+                };
+                match result {
+                    Some(result) => Ok(Value::Integer(result)),
+                    None if self.arithmetic == Arithmetic::Promoting => {
+                        Ok(Value::from_big(BigInt::from_i64(value).negate()))
+                    }
+                    None => self.error(
+                        format!("Integer overflow in unary '-' for {value}."),
+                        VMError::IntegerOverflow,
+                    ),
+                }
+            }
+            Value::Real(value) => Ok(Value::Real(-value)),
+            Value::BigInt(value) => Ok(Value::from_big(value.negate())),
+            Value::Nil => self.error(
+                "Unable to use unary '-' for nil.".to_string(),
+                VMError::UnaryOperator,
+            ),
+            value => self.error(
+                format!("Unable to use unary '-' for {value} value."),
+                VMError::UnaryOperator,
+            ),
+        }
+    }
+
+    fn shift_amount(&mut self, operator: &str, l: &Value, r: i64) -> VMResult<u32> {
+        if r < 0 {
+            self.error(format!("Unable to use '{operator}' operator for {l} and {r}, right hand side must be positive."), VMError::BinaryOperator)
+        } else if r > u32::MAX as i64 {
+            self.error(format!("Unable to use '{operator}' operator for {l} and {r}, right hand side value to big."), VMError::BinaryOperator)
+        } else {
+            Ok(r as u32)
         }
     }
 
     fn op_shift_left(&mut self, l: Value, r: Value) -> VMResult<Value> {
         match (l, r) {
             (Value::Integer(l), Value::Integer(r)) => {
-                if r < 0 {
-                    self.error(format!("Unable to use '<<' operator for {l} and {r}, right hand side must be positive."), VMError::BinaryOperator)
-                } else if r > u32::MAX as i64 {
-                    self.error(format!("Unable to use '<<' operator for {l} and {r}, right hand side value to big."), VMError::BinaryOperator)
+                let r = self.shift_amount("<<", &Value::Integer(l), r)?;
+                let result = l.wrapping_shl(r);
+                if self.arithmetic == Arithmetic::Promoting && (r >= 64 || result >> r != l) {
+                    Ok(Value::from_big(BigInt::from_i64(l).shift_left(r)))
                 } else {
-                    Ok(Value::Integer(l.wrapping_shl(r as u32)))
+                    Ok(Value::Integer(result))
                 }
             }
-            _ => self.op_error("<<", l, r),
+            (Value::BigInt(l), Value::Integer(r)) => {
+                let r = self.shift_amount("<<", &Value::BigInt(l.clone()), r)?;
+                Ok(Value::from_big(l.shift_left(r)))
+            }
+            (l, r) => self.op_error("<<", l, r),
         }
     }
 
     fn op_shift_right(&mut self, l: Value, r: Value) -> VMResult<Value> {
         match (l, r) {
             (Value::Integer(l), Value::Integer(r)) => {
-                if r < 0 {
-                    self.error(format!("Unable to use '>>' operator for {l} and {r}, right hand side must be positive."), VMError::BinaryOperator)
-                } else if r > u32::MAX as i64 {
-                    self.error(format!("Unable to use '>>' operator for {l} and {r}, right hand side value to big."), VMError::BinaryOperator)
-                } else {
-                    Ok(Value::Integer(l.wrapping_shr(r as u32)))
-                }
+                let r = self.shift_amount(">>", &Value::Integer(l), r)?;
+                Ok(Value::Integer(l.wrapping_shr(r)))
+            }
+            (Value::BigInt(l), Value::Integer(r)) => {
+                let r = self.shift_amount(">>", &Value::BigInt(l.clone()), r)?;
+                Ok(Value::from_big(l.shift_right(r)))
             }
-            _ => self.op_error(">>", l, r),
+            (l, r) => self.op_error(">>", l, r),
+        }
+    }
+
+    pub fn call_native(&mut self, index: u16, count: u8) -> VMResult<()> {
+        let mut arguments = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            arguments.push(self.pop()?);
+        }
+        arguments.reverse();
+        let result = self.call_native_with(index, &arguments)?;
+        self.push(result)
+    }
+
+    pub fn call_native_with(&mut self, index: u16, arguments: &[Value]) -> VMResult<Value> {
+        let (arity, function) = self.natives.get(index).ok_or(VMError::UnknownNative)?;
+        if arity as usize != arguments.len() {
+            let name = self.natives.name(index).unwrap_or_default().to_string();
+            return self.error(
+                format!(
+                    "Native function '{name}' expects {arity} argument(s), found {}.",
+                    arguments.len()
+                ),
+                VMError::NativeCall,
+            );
+        }
+        function(self, arguments)
+    }
+
+    pub fn load_global(&mut self, index: u16) -> VMResult<()> {
+        let value = self
+            .globals
+            .get(index as usize)
+            .cloned()
+            .unwrap_or(Value::Void);
+        self.push(value)
+    }
+
+    pub fn store_global(&mut self, index: u16) -> VMResult<()> {
+        let value = self.pop()?;
+        let index = index as usize;
+        if index >= self.globals.len() {
+            self.globals.resize(index + 1, Value::Void);
+        }
+        self.globals[index] = value.clone();
+        self.push(value)
+    }
+
+    pub fn condition(&mut self, value: &Value) -> VMResult<bool> {
+        match value {
+            Value::Boolean(value) => Ok(*value),
+            value => self.error(
+                format!(
+                    "Expected boolean condition, found {} '{value}'.",
+                    value.kind()
+                ),
+                VMError::Condition,
+            ),
+        }
+    }
+
+    pub fn field(&mut self, name: &str) -> VMResult<()> {
+        let value = self.pop()?;
+        let field = self.field_of(value, name)?;
+        self.push(field)
+    }
+
+    pub fn field_of(&mut self, value: Value, name: &str) -> VMResult<Value> {
+        match value.field(name) {
+            Some(field) => Ok(field),
+            None if value == Value::Nil => self.error(
+                format!("Unable to read field '{name}' of nil."),
+                VMError::Field,
+            ),
+            None => self.error(
+                format!(
+                    "Unable to read field '{name}' of {} '{value}'.",
+                    value.kind()
+                ),
+                VMError::Field,
+            ),
         }
     }
 
+    pub fn throw(&mut self) -> VMResult<()> {
+        let value = self.pop()?;
+        self.throw_value(value)
+    }
+
+    pub fn throw_value<T>(&mut self, value: Value) -> VMResult<T> {
+        self.message = Some(format!("Uncaught exception '{value}'.").into_boxed_str());
+        self.exception = Some(value);
+        Err(VMError::Exception)
+    }
+
+    pub fn duplicate(&mut self) -> VMResult<()> {
+        let value = self.pop()?;
+        self.push(value.clone())?;
+        self.push(value)
+    }
+
+    pub fn swap(&mut self) -> VMResult<()> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(b)?;
+        self.push(a)
+    }
+
+    pub fn rotate(&mut self) -> VMResult<()> {
+        let c = self.pop()?;
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(c)?;
+        self.push(a)?;
+        self.push(b)
+    }
+
+    pub fn drop(&mut self) -> VMResult<()> {
+        self.pop().map(|_| ())
+    }
+
+    pub fn power(&mut self) -> VMResult<()> {
+        self.binary(Self::op_power)
+    }
+
+    pub fn negate(&mut self) -> VMResult<()> {
+        self.unary(Self::op_negate)
+    }
+
     pub fn addict(&mut self) -> VMResult<()> {
         self.binary(Self::op_addict)
     }
@@ -278,6 +855,14 @@ impl<S: Stack> State<S> {
         self.binary(Self::op_module)
     }
 
+    pub fn floor_divide(&mut self) -> VMResult<()> {
+        self.binary(Self::op_floor_divide)
+    }
+
+    pub fn euclid_module(&mut self) -> VMResult<()> {
+        self.binary(Self::op_euclid_module)
+    }
+
     pub fn less(&mut self) -> VMResult<()> {
         self.binary(Self::op_less)
     }
@@ -321,4 +906,72 @@ impl<S: Stack> State<S> {
     pub fn shift_right(&mut self) -> VMResult<()> {
         self.binary(Self::op_shift_right)
     }
+
+    fn immediate<F>(&mut self, value: i64, f: F) -> VMResult<()>
+    where
+        F: Fn(&mut Self, Value, Value) -> VMResult<Value>,
+    {
+        let left = self.pop()?;
+        let result = f(self, left, Value::Integer(value))?;
+        self.push(result)
+    }
+
+    pub fn addict_immediate(&mut self, value: i64) -> VMResult<()> {
+        self.immediate(value, Self::op_addict)
+    }
+
+    pub fn subtract_immediate(&mut self, value: i64) -> VMResult<()> {
+        self.immediate(value, Self::op_subtract)
+    }
+
+    pub fn multiply_immediate(&mut self, value: i64) -> VMResult<()> {
+        self.immediate(value, Self::op_multiply)
+    }
+
+    pub fn compare(&mut self, opcode: u8) -> VMResult<bool> {
+        if !matches!(opcode, LS | GR | LE | GE | EQ | NE) {
+            return Err(VMError::UnknownInstruction);
+        }
+        let right = self.pop()?;
+        let left = self.pop()?;
+        let result = self.apply(opcode, left, right)?;
+        self.condition(&result)
+    }
+
+    pub fn apply(&mut self, opcode: u8, l: Value, r: Value) -> VMResult<Value> {
+        match opcode {
+            ADD => self.op_addict(l, r),
+            SUB => self.op_subtract(l, r),
+            MUL => self.op_multiply(l, r),
+            DIV => self.op_divide(l, r),
+            MOD => self.op_module(l, r),
+            FDV => self.op_floor_divide(l, r),
+            EMD => self.op_euclid_module(l, r),
+            POW => self.op_power(l, r),
+            AND => self.op_and(l, r),
+            OR => self.op_or(l, r),
+            XOR => self.op_xor(l, r),
+            SHL => self.op_shift_left(l, r),
+            SHR => self.op_shift_right(l, r),
+            LS => self.op_less(l, r),
+            GR => self.op_greater(l, r),
+            LE => self.op_less_equals(l, r),
+            GE => self.op_greater_equals(l, r),
+            EQ => self.op_equals(l, r),
+            NE => self.op_not_equals(l, r),
+            _ => Err(VMError::UnknownInstruction),
+        }
+    }
+
+    pub fn apply_negate(&mut self, value: Value) -> VMResult<Value> {
+        self.op_negate(value)
+    }
+
+    pub fn increment_local(&mut self, index: u8, value: i64) -> VMResult<()> {
+        let base = self.frames.last().ok_or(VMError::Frame)?.base;
+        let local = self.stack.get(base + index as usize)?;
+        let result = self.op_addict(local, Value::Integer(value))?;
+        self.stack.set(base + index as usize, result.clone())?;
+        self.push(result)
+    }
 }
@@ -1,9 +1,14 @@
+use crate::bigint::BigInt;
+
 #[derive(PartialEq)]
 pub enum Token {
     Integer(i64),
+    BigInteger(BigInt),
     Real(f64),
     Single(u8),
     Double(u8, u8),
+    Identifier(Box<str>),
+    Error(Box<str>),
 }
 
 pub type Pos = core::ops::Range<usize>;
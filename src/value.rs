@@ -1,20 +1,245 @@
-use core::fmt;
+use core::{cell::RefCell, cmp::Ordering, fmt};
+use std::rc::Rc;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use crate::bigint::BigInt;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Void,
+    Nil,
     Boolean(bool),
     Integer(i64),
     Real(f64),
+    BigInt(Rc<BigInt>),
+    String(Rc<str>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Vec<(Value, Value)>>>),
+    Ok(Rc<Value>),
+    Err(Rc<Value>),
+}
+
+impl Value {
+    pub fn from_big(value: BigInt) -> Self {
+        match value.to_i64() {
+            Some(value) => Value::Integer(value),
+            None => Value::BigInt(Rc::new(value)),
+        }
+    }
+
+    pub fn to_big(&self) -> Option<BigInt> {
+        match self {
+            Value::Integer(value) => Some(BigInt::from_i64(*value)),
+            Value::BigInt(value) => Some(value.as_ref().clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Value::Integer(_) | Value::Real(_) | Value::BigInt(_))
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Value::Void => 0,
+            Value::Nil => 1,
+            Value::Boolean(_) => 2,
+            Value::Integer(_) | Value::Real(_) | Value::BigInt(_) => 3,
+            Value::String(_) => 4,
+            Value::List(_) => 5,
+            Value::Map(_) => 6,
+            Value::Ok(_) => 7,
+            Value::Err(_) => 8,
+        }
+    }
+
+    fn compare_numbers(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Integer(l), Value::Integer(r)) => Some(l.cmp(r)),
+            (Value::Integer(l), Value::Real(r)) => compare_integer_real(*l, *r),
+            (Value::Real(l), Value::Integer(r)) => {
+                compare_integer_real(*r, *l).map(Ordering::reverse)
+            }
+            (Value::Real(l), Value::Real(r)) => l.partial_cmp(r),
+            (Value::BigInt(l), Value::Real(r)) => l.to_f64().partial_cmp(r),
+            (Value::Real(l), Value::BigInt(r)) => l.partial_cmp(&r.to_f64()),
+            (l, r) => Some(l.to_big()?.cmp(&r.to_big()?)),
+        }
+    }
+
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Void, Value::Void) | (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Ok(l), Value::Ok(r)) | (Value::Err(l), Value::Err(r)) => l.equals(r),
+            (Value::List(l), Value::List(r)) => {
+                let (l, r) = (l.borrow(), r.borrow());
+                l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| l.equals(r))
+            }
+            (Value::Map(l), Value::Map(r)) => {
+                let (l, r) = (l.borrow(), r.borrow());
+                l.len() == r.len()
+                    && l.iter().all(|(key, value)| {
+                        r.iter()
+                            .any(|(k, v)| k.total_cmp(key).is_eq() && v.equals(value))
+                    })
+            }
+            (l, r) if l.is_number() && r.is_number() => {
+                l.compare_numbers(r).is_some_and(Ordering::is_eq)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Void, Value::Void) | (Value::Nil, Value::Nil) => Some(Ordering::Equal),
+            (Value::Boolean(l), Value::Boolean(r)) => Some(l.cmp(r)),
+            (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+            (Value::List(l), Value::List(r)) => {
+                let (l, r) = (l.borrow(), r.borrow());
+                for (l, r) in l.iter().zip(r.iter()) {
+                    match l.compare(r)? {
+                        Ordering::Equal => continue,
+                        ordering => return Some(ordering),
+                    }
+                }
+                Some(l.len().cmp(&r.len()))
+            }
+            (l, r) if l.is_number() && r.is_number() => l.compare_numbers(r),
+            _ => None,
+        }
+    }
+
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Ok(l), Value::Ok(r)) | (Value::Err(l), Value::Err(r)) => l.total_cmp(r),
+            (Value::List(l), Value::List(r)) => {
+                let (l, r) = (l.borrow(), r.borrow());
+                l.iter()
+                    .zip(r.iter())
+                    .map(|(l, r)| l.total_cmp(r))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or_else(|| l.len().cmp(&r.len()))
+            }
+            (Value::Map(l), Value::Map(r)) => {
+                let sorted = |map: &Vec<(Value, Value)>| {
+                    let mut pairs = map.clone();
+                    pairs.sort_by(|(l, _), (r, _)| l.total_cmp(r));
+                    pairs
+                };
+                let (l, r) = (sorted(&l.borrow()), sorted(&r.borrow()));
+                l.iter()
+                    .zip(r.iter())
+                    .map(|((lk, lv), (rk, rv))| lk.total_cmp(rk).then_with(|| lv.total_cmp(rv)))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or_else(|| l.len().cmp(&r.len()))
+            }
+            (l, r) if l.is_number() && r.is_number() => {
+                let is_nan = |value: &Value| matches!(value, Value::Real(value) if value.is_nan());
+                match (is_nan(l), is_nan(r)) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Greater,
+                    (false, true) => Ordering::Less,
+                    (false, false) => l.compare_numbers(r).unwrap_or(Ordering::Equal),
+                }
+            }
+            (l, r) => l.compare(r).unwrap_or_else(|| l.rank().cmp(&r.rank())),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Void => "void",
+            Value::Nil => "nil",
+            Value::Boolean(_) => "boolean",
+            Value::Integer(_) | Value::BigInt(_) => "integer",
+            Value::Real(_) => "real",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Ok(_) | Value::Err(_) => "result",
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<Value> {
+        match self {
+            Value::Map(map) => Some(
+                map.borrow()
+                    .iter()
+                    .find(|(key, _)| matches!(key, Value::String(key) if key.as_ref() == name))
+                    .map_or(Value::Nil, |(_, value)| value.clone()),
+            ),
+            _ => None,
+        }
+    }
+
+    fn fmt_nested(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(value) => write!(f, "{value:?}"),
+            _ => write!(f, "{self}"),
+        }
+    }
+}
+
+fn compare_integer_real(l: i64, r: f64) -> Option<Ordering> {
+    if r.is_nan() {
+        None
+    } else if r >= 9223372036854775808.0 {
+        Some(Ordering::Less)
+    } else if r < -9223372036854775808.0 {
+        Some(Ordering::Greater)
+    } else {
+        let truncated = r.trunc();
+        Some(l.cmp(&(truncated as i64)).then_with(|| {
+            0f64.partial_cmp(&(r - truncated))
+                .unwrap_or(Ordering::Equal)
+        }))
+    }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Void => write!(f, "()"),
+            Value::Nil => write!(f, "nil"),
             Value::Boolean(value) => write!(f, "{value}"),
             Value::Integer(value) => write!(f, "{value}"),
             Value::Real(value) => write!(f, "{value}"),
+            Value::BigInt(value) => write!(f, "{value}"),
+            Value::String(value) => write!(f, "{value}"),
+            Value::List(list) => {
+                write!(f, "[")?;
+                for (i, value) in list.borrow().iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.fmt_nested(f)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.fmt_nested(f)?;
+                    write!(f, ": ")?;
+                    value.fmt_nested(f)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Ok(value) => {
+                write!(f, "Ok(")?;
+                value.fmt_nested(f)?;
+                write!(f, ")")
+            }
+            Value::Err(value) => {
+                write!(f, "Err(")?;
+                value.fmt_nested(f)?;
+                write!(f, ")")
+            }
         }
     }
 }
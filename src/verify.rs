@@ -0,0 +1,192 @@
+use core::fmt;
+
+use crate::{
+    get::{GetByte, GetData},
+    handler::HandlerTable,
+    opcode::*,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum VerifyError {
+    UnknownOpcode {
+        address: usize,
+        opcode: u8,
+    },
+    MissingOperand {
+        address: usize,
+    },
+    InvalidTarget {
+        address: usize,
+        target: usize,
+    },
+    StackUnderflow {
+        address: usize,
+    },
+    InconsistentStack {
+        address: usize,
+        expected: usize,
+        found: usize,
+    },
+    FallsOffEnd {
+        address: usize,
+    },
+    InvalidHandler {
+        start: usize,
+        end: usize,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::UnknownOpcode { address, opcode } => {
+                write!(f, "Unknown opcode {opcode:#04X} at {address}.")
+            }
+            VerifyError::MissingOperand { address } => {
+                write!(f, "Incomplete operands of instruction at {address}.")
+            }
+            VerifyError::InvalidTarget { address, target } => write!(
+                f,
+                "Instruction at {address} targets {target}, which is not an instruction boundary."
+            ),
+            VerifyError::StackUnderflow { address } => {
+                write!(f, "Instruction at {address} pops from an empty stack.")
+            }
+            VerifyError::InconsistentStack {
+                address,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Stack depth at {address} is {found} on one path and {expected} on another."
+            ),
+            VerifyError::FallsOffEnd { address } => {
+                write!(f, "Execution runs past the end of code after {address}.")
+            }
+            VerifyError::InvalidHandler { start, end } => write!(
+                f,
+                "Handler at {start} ends at {end}, which is not an instruction boundary after it."
+            ),
+        }
+    }
+}
+
+struct Instruction {
+    opcode: u8,
+    info: Info,
+    size: usize,
+}
+
+fn decode<G: GetByte>(program: &G) -> Result<Vec<Option<Instruction>>, VerifyError> {
+    let mut instructions = Vec::new();
+    let mut address = 0;
+    while let Some(opcode) = program.get_byte(address) {
+        let info = info(opcode).ok_or(VerifyError::UnknownOpcode { address, opcode })?;
+        let size = info
+            .size(program, address)
+            .ok_or(VerifyError::MissingOperand { address })?;
+        instructions.push(Some(Instruction { opcode, info, size }));
+        instructions.extend((1..size).map(|_| None));
+        address += size;
+    }
+    Ok(instructions)
+}
+
+fn target<G: GetByte>(
+    program: &G,
+    instructions: &[Option<Instruction>],
+    address: usize,
+) -> Result<usize, VerifyError> {
+    let offset = match instructions.get(address) {
+        Some(Some(Instruction { opcode: CJP, .. })) => 2,
+        _ => 1,
+    };
+    let target = GetData::<u32>::get_data(program, address + offset)
+        .ok_or(VerifyError::MissingOperand { address })? as usize;
+    match instructions.get(target) {
+        Some(Some(_)) => Ok(target),
+        _ => Err(VerifyError::InvalidTarget { address, target }),
+    }
+}
+
+pub fn verify<G: GetByte>(program: &G, handlers: &HandlerTable) -> Result<(), VerifyError> {
+    let instructions = decode(program)?;
+    let mut pending = vec![(0, 0)];
+    for handler in handlers.iter() {
+        for address in [handler.start, handler.target] {
+            if !matches!(instructions.get(address), Some(Some(_))) {
+                return Err(VerifyError::InvalidTarget {
+                    address: handler.start,
+                    target: address,
+                });
+            }
+        }
+        let boundary = handler.end == instructions.len()
+            || matches!(instructions.get(handler.end), Some(Some(_)));
+        if !boundary || handler.end < handler.start {
+            return Err(VerifyError::InvalidHandler {
+                start: handler.start,
+                end: handler.end,
+            });
+        }
+        pending.push((handler.target, handler.depth + 1));
+    }
+    for (address, instruction) in instructions.iter().enumerate() {
+        if let Some(Instruction { opcode: CAL, .. }) = instruction {
+            pending.push((target(program, &instructions, address)?, 0));
+        }
+    }
+
+    let mut depths = vec![None; instructions.len()];
+    while let Some((address, depth)) = pending.pop() {
+        let instruction = match instructions.get(address) {
+            Some(Some(instruction)) => instruction,
+            _ => return Err(VerifyError::FallsOffEnd { address }),
+        };
+        match depths[address] {
+            Some(expected) if expected == depth => continue,
+            Some(expected) => {
+                return Err(VerifyError::InconsistentStack {
+                    address,
+                    expected,
+                    found: depth,
+                })
+            }
+            None => depths[address] = Some(depth),
+        }
+        let mut pops = instruction.info.pops as usize;
+        if matches!(instruction.opcode, CLN | CAL) {
+            let count: u8 = program
+                .get_data(address + instruction.size - 1)
+                .ok_or(VerifyError::MissingOperand { address })?;
+            pops += count as usize;
+        }
+        if depth < pops {
+            return Err(VerifyError::StackUnderflow { address });
+        }
+        if instruction.opcode == CJP {
+            let compare: u8 = program
+                .get_data(address + 1)
+                .ok_or(VerifyError::MissingOperand { address })?;
+            if !matches!(compare, LS | GR | LE | GE | EQ | NE) {
+                return Err(VerifyError::UnknownOpcode {
+                    address,
+                    opcode: compare,
+                });
+            }
+        }
+        let next = address + instruction.size;
+        let after = depth - pops + instruction.info.pushes as usize;
+        match instruction.opcode {
+            END | THR | RET => {}
+            JMP => pending.push((target(program, &instructions, address)?, depth)),
+            JFP | JNN | JIN | CJP => {
+                let taken = depth - pops + 1;
+                pending.push((target(program, &instructions, address)?, taken));
+                pending.push((next, after));
+            }
+            _ => pending.push((next, after)),
+        }
+    }
+    Ok(())
+}
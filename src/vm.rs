@@ -1,24 +1,67 @@
 use crate::{
+    bigint::BigInt,
+    decode::{Decoded, Instruction},
     get::{GetByte, GetData},
+    handler::HandlerTable,
     opcode::*,
     state::*,
     state::{VMError, VMResult},
     value::Value,
 };
 
-fn step<S: Stack, G: GetByte>(state: &mut State<S>, program: &G) -> VMResult<bool> {
+pub enum Status {
+    Finished(Value),
+    Yielded,
+    Paused,
+}
+
+pub fn step<S: Stack, G: GetByte>(state: &mut State<S>, program: &G) -> VMResult<Option<Status>> {
     let opcode = program
         .get_byte(state.program_counter)
         .ok_or(VMError::OpcodeFetch)?;
     match opcode {
-        END => Ok(false),
+        END => Ok(Some(Status::Finished(state.pop()?))),
         LDI => {
             let value = program
                 .get_data(state.program_counter + 1)
                 .ok_or(VMError::OpcodeFetch)?;
             state.push(Value::Integer(value))?;
             state.program_counter += 1 + core::mem::size_of_val(&value);
-            Ok(true)
+            Ok(None)
+        }
+        LD0 => state.single(|state| state.push(Value::Integer(0))),
+        LD1 => state.single(|state| state.push(Value::Integer(1))),
+        LDI8 => {
+            let value: i8 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            state.push(Value::Integer(value as i64))?;
+            state.program_counter += 2;
+            Ok(None)
+        }
+        LDI16 => {
+            let value: i16 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            state.push(Value::Integer(value as i64))?;
+            state.program_counter += 3;
+            Ok(None)
+        }
+        LDI32 => {
+            let value: i32 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            state.push(Value::Integer(value as i64))?;
+            state.program_counter += 5;
+            Ok(None)
+        }
+        LDC => {
+            let index: u16 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            state.load_constant(index)?;
+            state.program_counter += 3;
+            Ok(None)
         }
         LDR => {
             let value = program
@@ -26,13 +69,40 @@ fn step<S: Stack, G: GetByte>(state: &mut State<S>, program: &G) -> VMResult<boo
                 .ok_or(VMError::OpcodeFetch)?;
             state.push(Value::Real(value))?;
             state.program_counter += 1 + core::mem::size_of_val(&value);
-            Ok(true)
+            Ok(None)
+        }
+        LDB => {
+            let negative: u8 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            let count: u16 = program
+                .get_data(state.program_counter + 2)
+                .ok_or(VMError::OpcodeFetch)?;
+            let mut magnitude = Vec::with_capacity(count as usize);
+            for i in 0..count as usize {
+                let limb: u32 = program
+                    .get_data(state.program_counter + 4 + i * 4)
+                    .ok_or(VMError::OpcodeFetch)?;
+                magnitude.push(limb);
+            }
+            state.push(Value::from_big(BigInt::from_parts(
+                negative != 0,
+                magnitude,
+            )))?;
+            state.program_counter += 4 + count as usize * 4;
+            Ok(None)
         }
+        LDT => state.single(|state| state.push(Value::Boolean(true))),
+        LDF => state.single(|state| state.push(Value::Boolean(false))),
+        LDV => state.single(|state| state.push(Value::Void)),
+        LDN => state.single(|state| state.push(Value::Nil)),
         ADD => state.single(State::addict),
         MUL => state.single(State::multiply),
         SUB => state.single(State::subtract),
         DIV => state.single(State::divide),
         MOD => state.single(State::module),
+        FDV => state.single(State::floor_divide),
+        EMD => state.single(State::euclid_module),
         LS => state.single(State::less),
         GR => state.single(State::greater),
         LE => state.single(State::less_equals),
@@ -44,11 +114,377 @@ fn step<S: Stack, G: GetByte>(state: &mut State<S>, program: &G) -> VMResult<boo
         XOR => state.single(State::xor),
         SHL => state.single(State::shift_left),
         SHR => state.single(State::shift_right),
+        POW => state.single(State::power),
+        NEG => state.single(State::negate),
+        POP => state.single(State::drop),
+        THR => state.single(State::throw),
+        RET => {
+            state.ret()?;
+            Ok(None)
+        }
+        UNW => state.unwrap_result(),
+        MKO => state.single(|state| {
+            let value = state.pop()?;
+            state.push(Value::Ok(value.into()))
+        }),
+        MKE => state.single(|state| {
+            let value = state.pop()?;
+            state.push(Value::Err(value.into()))
+        }),
+        CAL => {
+            let address: u32 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            let count: u8 = program
+                .get_data(state.program_counter + 5)
+                .ok_or(VMError::OpcodeFetch)?;
+            let return_address = state.program_counter + 6;
+            state.call(address as usize, count, return_address)?;
+            Ok(None)
+        }
+        LCL | LDL | STL => {
+            let operand: u8 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            match opcode {
+                LCL => state.reserve(operand)?,
+                LDL => state.load_local(operand)?,
+                _ => state.store_local(operand)?,
+            }
+            state.program_counter += 2;
+            Ok(None)
+        }
+        DUP => state.single(State::duplicate),
+        SWP => state.single(State::swap),
+        ROT => state.single(State::rotate),
+        JMP => {
+            let address: u32 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            state.program_counter = address as usize;
+            Ok(None)
+        }
+        JFP => {
+            let address: u32 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            let value = state.pop()?;
+            if state.condition(&value)? {
+                state.program_counter += 5;
+            } else {
+                state.push(value)?;
+                state.program_counter = address as usize;
+            }
+            Ok(None)
+        }
+        JIN => {
+            let address: u32 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            let value = state.pop()?;
+            let is_nil = value == Value::Nil;
+            state.push(value)?;
+            if is_nil {
+                state.program_counter = address as usize;
+            } else {
+                state.program_counter += 5;
+            }
+            Ok(None)
+        }
+        FLD => {
+            let length: u16 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            let start = state.program_counter + 3;
+            let bytes = (start..start + length as usize)
+                .map(|address| program.get_byte(address))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or(VMError::OpcodeFetch)?;
+            state.field(&String::from_utf8_lossy(&bytes))?;
+            state.program_counter = start + length as usize;
+            Ok(None)
+        }
+        JNN => {
+            let address: u32 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            let value = state.pop()?;
+            if value == Value::Nil {
+                state.program_counter += 5;
+            } else {
+                state.push(value)?;
+                state.program_counter = address as usize;
+            }
+            Ok(None)
+        }
+        CLN => {
+            let index: u16 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            let count: u8 = program
+                .get_data(state.program_counter + 3)
+                .ok_or(VMError::OpcodeFetch)?;
+            state.call_native(index, count)?;
+            state.program_counter += 4;
+            Ok(None)
+        }
+        LDG => {
+            let index: u16 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            state.load_global(index)?;
+            state.program_counter += 3;
+            Ok(None)
+        }
+        STG => {
+            let index: u16 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            state.store_global(index)?;
+            state.program_counter += 3;
+            Ok(None)
+        }
+        YLD => {
+            state.push(Value::Void)?;
+            state.program_counter += 1;
+            Ok(Some(Status::Yielded))
+        }
+        ADI | SBI | MLI => {
+            let value: i32 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            match opcode {
+                ADI => state.addict_immediate(value as i64)?,
+                SBI => state.subtract_immediate(value as i64)?,
+                _ => state.multiply_immediate(value as i64)?,
+            }
+            state.program_counter += 5;
+            Ok(None)
+        }
+        CJP => {
+            let compare: u8 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            let address: u32 = program
+                .get_data(state.program_counter + 2)
+                .ok_or(VMError::OpcodeFetch)?;
+            if state.compare(compare)? {
+                state.program_counter += 6;
+            } else {
+                state.push(Value::Boolean(false))?;
+                state.program_counter = address as usize;
+            }
+            Ok(None)
+        }
+        INL => {
+            let index: u8 = program
+                .get_data(state.program_counter + 1)
+                .ok_or(VMError::OpcodeFetch)?;
+            let value: i32 = program
+                .get_data(state.program_counter + 2)
+                .ok_or(VMError::OpcodeFetch)?;
+            state.increment_local(index, value as i64)?;
+            state.program_counter += 6;
+            Ok(None)
+        }
         _ => Err(VMError::UnknownInstruction),
     }
 }
 
+pub fn run_for<S: Stack, G: GetByte>(
+    state: &mut State<S>,
+    program: &G,
+    steps: usize,
+) -> VMResult<Status> {
+    for i in 0..steps {
+        if i != 0 && state.breakpoints.contains(&state.program_counter) {
+            return Ok(Status::Paused);
+        }
+        if let Some(status) = step(state, program)? {
+            return Ok(status);
+        }
+    }
+    Ok(Status::Paused)
+}
+
+pub fn run_until<S: Stack, G: GetByte>(state: &mut State<S>, program: &G) -> VMResult<Status> {
+    run_for(state, program, usize::MAX)
+}
+
 pub fn run<S: Stack, G: GetByte>(state: &mut State<S>, program: &G) -> VMResult<Value> {
-    while step(state, program)? {}
-    state.pop()
+    loop {
+        if let Some(Status::Finished(value)) = step(state, program)? {
+            return Ok(value);
+        }
+    }
+}
+
+pub fn execute_for<S: Stack>(
+    state: &mut State<S>,
+    program: &Decoded,
+    steps: &mut usize,
+) -> VMResult<Status> {
+    let budget = *steps;
+    while *steps != 0 {
+        if *steps != budget && !state.breakpoints.is_empty() {
+            let address = program.addresses.get(state.program_counter);
+            if address.is_some_and(|address| state.breakpoints.contains(address)) {
+                return Ok(Status::Paused);
+            }
+        }
+        *steps -= 1;
+        let instruction = program
+            .code
+            .get(state.program_counter)
+            .ok_or(VMError::OpcodeFetch)?;
+        match instruction {
+            Instruction::End => return Ok(Status::Finished(state.pop()?)),
+            Instruction::Integer(value) => state.push(Value::Integer(*value))?,
+            Instruction::Real(value) => state.push(Value::Real(*value))?,
+            Instruction::Constant(index) => state.load_constant(*index)?,
+            Instruction::BigInteger(value) => state.push(value.clone())?,
+            Instruction::True => state.push(Value::Boolean(true))?,
+            Instruction::False => state.push(Value::Boolean(false))?,
+            Instruction::Void => state.push(Value::Void)?,
+            Instruction::Yield => {
+                state.push(Value::Void)?;
+                state.program_counter += 1;
+                return Ok(Status::Yielded);
+            }
+            Instruction::Nil => state.push(Value::Nil)?,
+            Instruction::Add => state.addict()?,
+            Instruction::Multiply => state.multiply()?,
+            Instruction::Subtract => state.subtract()?,
+            Instruction::Divide => state.divide()?,
+            Instruction::Module => state.module()?,
+            Instruction::FloorDivide => state.floor_divide()?,
+            Instruction::EuclidModule => state.euclid_module()?,
+            Instruction::Power => state.power()?,
+            Instruction::Negate => state.negate()?,
+            Instruction::Less => state.less()?,
+            Instruction::Greater => state.greater()?,
+            Instruction::LessEqual => state.less_equals()?,
+            Instruction::GreaterEqual => state.greater_equals()?,
+            Instruction::Equal => state.equals()?,
+            Instruction::NotEqual => state.not_equals()?,
+            Instruction::And => state.and()?,
+            Instruction::Or => state.or()?,
+            Instruction::Xor => state.xor()?,
+            Instruction::ShiftLeft => state.shift_left()?,
+            Instruction::ShiftRight => state.shift_right()?,
+            Instruction::Pop => state.drop()?,
+            Instruction::Duplicate => state.duplicate()?,
+            Instruction::Swap => state.swap()?,
+            Instruction::Rotate => state.rotate()?,
+            Instruction::CallNative(index, count) => state.call_native(*index, *count)?,
+            Instruction::LoadGlobal(index) => state.load_global(*index)?,
+            Instruction::StoreGlobal(index) => state.store_global(*index)?,
+            Instruction::Jump(target) => {
+                state.program_counter = *target;
+                continue;
+            }
+            Instruction::JumpIfFalse(target) => {
+                let value = state.pop()?;
+                if !state.condition(&value)? {
+                    state.push(value)?;
+                    state.program_counter = *target;
+                    continue;
+                }
+            }
+            Instruction::JumpIfNotNil(target) => {
+                let value = state.pop()?;
+                if value != Value::Nil {
+                    state.push(value)?;
+                    state.program_counter = *target;
+                    continue;
+                }
+            }
+            Instruction::JumpIfNil(target) => {
+                let value = state.pop()?;
+                let is_nil = value == Value::Nil;
+                state.push(value)?;
+                if is_nil {
+                    state.program_counter = *target;
+                    continue;
+                }
+            }
+            Instruction::Field(name) => state.field(name)?,
+            Instruction::Throw => state.throw()?,
+            Instruction::Call(target, count) => {
+                state.call(*target, *count, state.program_counter + 1)?;
+                continue;
+            }
+            Instruction::Return => {
+                state.ret()?;
+                continue;
+            }
+            Instruction::LoadLocal(index) => state.load_local(*index)?,
+            Instruction::StoreLocal(index) => state.store_local(*index)?,
+            Instruction::Reserve(count) => state.reserve(*count)?,
+            Instruction::MakeOk => {
+                let value = state.pop()?;
+                state.push(Value::Ok(value.into()))?;
+            }
+            Instruction::MakeErr => {
+                let value = state.pop()?;
+                state.push(Value::Err(value.into()))?;
+            }
+            Instruction::Unwrap => {
+                state.unwrap_result()?;
+                continue;
+            }
+            Instruction::AddImmediate(value) => state.addict_immediate(*value)?,
+            Instruction::SubtractImmediate(value) => state.subtract_immediate(*value)?,
+            Instruction::MultiplyImmediate(value) => state.multiply_immediate(*value)?,
+            Instruction::CompareJump(compare, target) => {
+                if !state.compare(*compare)? {
+                    state.push(Value::Boolean(false))?;
+                    state.program_counter = *target;
+                    continue;
+                }
+            }
+            Instruction::IncrementLocal(index, value) => state.increment_local(*index, *value)?,
+        }
+        state.program_counter += 1;
+    }
+    Ok(Status::Paused)
+}
+
+pub fn execute<S: Stack>(state: &mut State<S>, program: &Decoded) -> VMResult<Status> {
+    let mut steps = usize::MAX;
+    execute_for(state, program, &mut steps)
+}
+
+pub fn unwind<S: Stack>(
+    state: &mut State<S>,
+    handlers: &HandlerTable,
+    error: VMError,
+) -> VMResult<()> {
+    let mut address = state.program_counter;
+    let mut frames = state.frames.len();
+    let handler = loop {
+        if let Some(handler) = handlers.find(address) {
+            break handler;
+        }
+        match frames.checked_sub(1) {
+            Some(frame) => {
+                address = state.frames[frame].return_address - 1;
+                frames = frame;
+            }
+            None => return Err(error),
+        }
+    };
+    let value = match state.exception.take() {
+        Some(value) => value,
+        None => match state.message.take() {
+            Some(message) => Value::String(message.into()),
+            None => Value::String(error.to_string().into()),
+        },
+    };
+    state.frames.truncate(frames);
+    let start = state.frames.last().map_or(0, |frame| frame.start);
+    state.truncate(start + handler.depth)?;
+    state.push(value)?;
+    state.program_counter = handler.target;
+    Ok(())
 }